@@ -14,6 +14,8 @@ pub struct Device {
     pub id: String,
     pub dtid: String,
     pub name: String,
+    /// Only known for devices reporting their presence.
+    pub connected: Option<bool>,
 }
 
 impl Akc {
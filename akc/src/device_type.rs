@@ -11,10 +11,10 @@ paginated_wrapper!(DataDeviceTypes, DeviceTypes, device_types, DeviceType);
 
 #[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct DeviceType {
-    id: String,
+    pub id: String,
     #[serde(rename = "uniqueName")]
-    unique_name: String,
-    name: String,
+    pub unique_name: String,
+    pub name: String,
 }
 
 impl Akc {
@@ -1,6 +1,6 @@
 use std;
 use hyper;
-use hyper::header::Headers;
+use hyper::header::{Headers, ContentType};
 use serde;
 use serde_json;
 use futures::future::*;
@@ -60,6 +60,103 @@ impl Akc {
             .boxed()
     }
 
+    pub fn post<Wrapper>(token: oauth2::Token,
+                         url: hyper::Url,
+                         body: String)
+                         -> Box<Future<Item = Wrapper::Data, Error = AkcClientError> + std::marker::Send>
+        where Wrapper: DataWrapper,
+              Wrapper: serde::de::DeserializeOwned,
+              Wrapper::Data: 'static
+    {
+        let mut headers = Self::auth_header(token);
+        headers.set(ContentType::json());
+        future_request::post_async::<AkcClientError>(url, headers, body)
+            .and_then(move |response| match StatusCode::from_u16(response.status_raw().0) {
+                          StatusCode::Ok => {
+                              let data_wrapper: Wrapper = match serde_json::from_reader(response) {
+                                  Ok(data_wrapper) => data_wrapper,
+                                  Err(error) => Err(error)?,
+                              };
+                              Ok(data_wrapper.data())
+                          }
+                          _ => {
+                              let error_wrapper: ErrorWrapper = match serde_json::from_reader(response) {
+                                  Ok(error_wrapper) => error_wrapper,
+                                  Err(error) => Err(error)?,
+                              };
+                              Err(error_wrapper)?
+                          }
+                      })
+            .boxed()
+    }
+
+    pub fn get_with_cursor<Wrapper>(token: oauth2::Token,
+                                    mut url: hyper::Url,
+                                    query_params: Vec<(String, String)>,
+                                    cursor: Option<String>)
+                                    -> Box<Future<Item = (Wrapper::Data, Option<String>), Error = AkcClientError> + std::marker::Send>
+        where Wrapper: CursorWrapper + DataWrapper,
+              Wrapper: serde::de::DeserializeOwned,
+              Wrapper::Data: 'static
+    {
+        url.query_pairs_mut().extend_pairs(query_params);
+        if let Some(cursor) = cursor {
+            url.query_pairs_mut().append_pair("offset", &cursor);
+        }
+        future_request::get_async::<AkcClientError>(url, Self::auth_header(token))
+            .and_then(move |response| match StatusCode::from_u16(response.status_raw().0) {
+                          StatusCode::Ok => {
+                              let data_wrapper: Wrapper = match serde_json::from_reader(response) {
+                                  Ok(data_wrapper) => data_wrapper,
+                                  Err(error) => Err(error)?,
+                              };
+                              let next = data_wrapper.next();
+                              Ok((data_wrapper.data(), next))
+                          }
+                          _ => {
+                              let error_wrapper: ErrorWrapper = match serde_json::from_reader(response) {
+                                  Ok(error_wrapper) => error_wrapper,
+                                  Err(error) => Err(error)?,
+                              };
+                              Err(error_wrapper)?
+                          }
+                      })
+            .boxed()
+    }
+
+    /// Follows the `next` cursor of each page, stopping after `max_pages`.
+    pub fn get_all_pages_with_cursor<Wrapper>
+        (token: oauth2::Token,
+         url: hyper::Url,
+         query_params: Vec<(String, String)>,
+         max_pages: u64)
+         -> Box<Future<Item = Vec<<<Wrapper as DataWrapper>::Data as Collection>::Collected>, Error = AkcClientError> + std::marker::Send>
+        where Wrapper: CursorWrapper + DataWrapper,
+              Wrapper: serde::de::DeserializeOwned,
+              Wrapper::Data: Collection + 'static
+    {
+        let stream = stream::unfold(Some(None), move |state: Option<Option<String>>| match state {
+            None => None,
+            Some(cursor) => {
+                let fut = Self::get_with_cursor::<Wrapper>(token.clone(), url.clone(), query_params.clone(), cursor)
+                    .map(|(data, next)| (data, next.map(Some)));
+                Some(fut)
+            }
+        });
+
+        stream
+            .take(max_pages)
+            .collect()
+            .and_then(|pages| {
+                          let mut acc = vec![];
+                          for page in pages {
+                              acc.extend(page.items());
+                          }
+
+                          Ok(acc)
+                      })
+            .boxed()
+    }
 
     pub fn get_paginated_with_params<Wrapper>(token: oauth2::Token,
                                               mut url: hyper::Url,
@@ -237,6 +334,17 @@ pub trait Collection {
     type Collected: std::marker::Send;
     fn items(self: Self) -> Vec<Self::Collected>;
 }
+impl<T: std::marker::Send> Collection for Vec<T> {
+    type Collected = T;
+    fn items(self: Self) -> Vec<Self::Collected> {
+        self
+    }
+}
+
+/// Pages that link to the following one with an opaque cursor instead of an offset.
+pub trait CursorWrapper {
+    fn next(self: &Self) -> Option<String>;
+}
 
 macro_rules! paginated_wrapper {
     ( $w:ident, $d:ident, $n:ident, $i:ident ) => {
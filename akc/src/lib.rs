@@ -8,6 +8,10 @@ extern crate serde;
 extern crate serde_json;
 
 extern crate futures;
+extern crate websocket;
+#[macro_use]
+extern crate lazy_static;
+extern crate transient_hashmap;
 
 extern crate oauth2;
 extern crate future_request;
@@ -15,11 +19,14 @@ extern crate future_request;
 #[macro_use]
 mod helpers;
 
-mod error;
+pub mod error;
 pub mod user;
 pub mod device;
 pub mod device_type;
+pub mod manifest;
 pub mod snapshot;
+pub mod message;
+pub mod live;
 
 #[derive(Debug, Clone)]
 pub struct Akc {}
@@ -28,6 +35,10 @@ impl Akc {
     pub fn base_url<'a>() -> &'a str {
         "https://api.artik.cloud/v1.1"
     }
+    /// Where authorization codes and refresh tokens are exchanged.
+    pub fn token_url<'a>() -> &'a str {
+        "https://accounts.artik.cloud/token"
+    }
 }
 
 
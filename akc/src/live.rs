@@ -0,0 +1,291 @@
+use std::cmp;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
+
+use hyper::Url;
+use hyper::header::Headers;
+use futures::{Future, Poll, Sink, Stream};
+use futures::sync::mpsc;
+use serde_json;
+use websocket::{ClientBuilder, OwnedMessage};
+use websocket::sync::Client;
+use websocket::stream::sync::NetworkStream;
+
+use oauth2;
+
+use Akc;
+use error::{AkcClientError, ErrorWrapper};
+use message::NormalizedMessage;
+
+/// Connections failing in a row before the stream gives up.
+const MAX_FAILURES: u32 = 5;
+const MAX_RECONNECT_DELAY: u64 = 60;
+
+/// Which messages to receive from the firehose.
+#[derive(Debug, Clone)]
+pub enum LiveFilter {
+    Devices(Vec<String>),
+    /// Every device of a user.
+    User(String),
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(untagged)]
+enum LiveFrame {
+    Message(NormalizedMessage),
+    Error(ErrorWrapper),
+    /// Heartbeats sent every 30 seconds, as `{"type": "ping", "ts": 1469046154862}`.
+    Other(serde_json::Value),
+}
+
+type Sender = mpsc::Sender<Result<NormalizedMessage, AkcClientError>>;
+
+/// Stops the thread listening to the connection once dropped, as it may otherwise only receive heartbeats for ever.
+struct LiveStream {
+    messages: Box<Stream<Item = NormalizedMessage, Error = AkcClientError> + Send>,
+    stopped: Arc<AtomicBool>,
+}
+impl Stream for LiveStream {
+    type Item = NormalizedMessage;
+    type Error = AkcClientError;
+
+    fn poll(&mut self) -> Poll<Option<NormalizedMessage>, AkcClientError> {
+        self.messages.poll()
+    }
+}
+impl Drop for LiveStream {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::SeqCst);
+    }
+}
+
+impl Akc {
+    pub fn live_url<'a>() -> &'a str {
+        "wss://api.artik.cloud/v1.1/live"
+    }
+
+    /// Messages sent by the devices matching `filter` from now on, reconnecting when the connection drops.
+    /// The stream ends with an error when AKC rejects the subscription or can't be reached anymore.
+    pub fn live(token: oauth2::Token, filter: LiveFilter) -> Box<Stream<Item = NormalizedMessage, Error = AkcClientError> + Send> {
+        live_from(Url::parse(Self::live_url()).unwrap(),
+                  token,
+                  filter,
+                  Duration::from_secs(1))
+    }
+}
+
+fn live_from(mut url: Url,
+             token: oauth2::Token,
+             filter: LiveFilter,
+             reconnect_delay: Duration)
+             -> Box<Stream<Item = NormalizedMessage, Error = AkcClientError> + Send> {
+    match filter {
+        LiveFilter::Devices(sdids) => url.query_pairs_mut().append_pair("sdids", &sdids.join(",")),
+        LiveFilter::User(uid) => url.query_pairs_mut().append_pair("uid", &uid),
+    };
+    // the token is sent in the handshake headers rather than in the url, that proxies and servers may log
+    let mut headers = Headers::new();
+    headers.set(token.bearer());
+    let (sender, receiver) = mpsc::channel(16);
+    let stopped = Arc::new(AtomicBool::new(false));
+    let listening_stopped = stopped.clone();
+    thread::spawn(move || listen(url, headers, sender, &listening_stopped, reconnect_delay));
+    Box::new(LiveStream {
+                 messages: Box::new(receiver.then(|item| match item {
+                                                      Ok(item) => item,
+                                                      Err(()) => Err(AkcClientError::InternalError("live stream stopped".to_string())),
+                                                  })),
+                 stopped,
+             })
+}
+
+/// Connects until the receiver is dropped or the connection keeps failing.
+fn listen(url: Url, headers: Headers, mut sender: Sender, stopped: &AtomicBool, reconnect_delay: Duration) {
+    let mut failures = 0;
+    let mut delay = reconnect_delay;
+    while !stopped.load(Ordering::SeqCst) {
+        match ClientBuilder::from_url(&url)
+                  .custom_headers(&headers)
+                  .connect(None) {
+            Ok(client) => {
+                failures = 0;
+                delay = reconnect_delay;
+                sender = match forward(client, sender, stopped) {
+                    Some(sender) => sender,
+                    None => return,
+                };
+            }
+            Err(err) => {
+                failures += 1;
+                if failures >= MAX_FAILURES {
+                    let error = AkcClientError::InternalError(format!("couldn't connect to AKC live: {:?}", err));
+                    let _ = sender.send(Err(error)).wait();
+                    return;
+                }
+            }
+        }
+        thread::sleep(delay);
+        delay = cmp::min(delay * 2, Duration::from_secs(MAX_RECONNECT_DELAY));
+    }
+}
+
+/// Forwards the messages of a connection until it is closed, giving back the sender to reconnect.
+/// Returns `None` when the stream should stop.
+fn forward(mut client: Client<Box<NetworkStream + Send>>, mut sender: Sender, stopped: &AtomicBool) -> Option<Sender> {
+    loop {
+        let frame = client.recv_message();
+        // checked on every frame, heartbeats included, as they keep coming when no device sends anything
+        if stopped.load(Ordering::SeqCst) {
+            let _ = client.send_message(&OwnedMessage::Close(None));
+            return None;
+        }
+        let text = match frame {
+            Ok(OwnedMessage::Text(text)) => text,
+            Ok(OwnedMessage::Ping(data)) => {
+                if client.send_message(&OwnedMessage::Pong(data)).is_err() {
+                    return Some(sender);
+                }
+                continue;
+            }
+            Ok(OwnedMessage::Close(_)) => {
+                let _ = client.send_message(&OwnedMessage::Close(None));
+                return Some(sender);
+            }
+            Ok(_) => continue,
+            Err(_) => return Some(sender),
+        };
+        let item = match serde_json::from_str::<LiveFrame>(&text) {
+            Ok(LiveFrame::Message(message)) => Ok(message),
+            Ok(LiveFrame::Error(error)) => {
+                let _ = sender.send(Err(AkcClientError::from(error))).wait();
+                return None;
+            }
+            Ok(LiveFrame::Other(_)) => continue,
+            Err(err) => Err(AkcClientError::from(err)),
+        };
+        sender = match sender.send(item).wait() {
+            Ok(sender) => sender,
+            Err(_) => return None,
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+    use std::thread;
+    use std::time::Duration;
+
+    use hyper::Url;
+    use hyper::header::{Authorization, Bearer};
+    use futures::{Future, Stream};
+    use websocket::OwnedMessage;
+    use websocket::sync::Server;
+
+    use oauth2;
+
+    use super::*;
+
+    fn message(mid: &str) -> String {
+        format!(r#"{{"mid": "{}", "sdid": "d1", "sdtid": "dt1", "ts": 1500000000000, "data": {{"temperature": 21.5}}}}"#,
+                mid)
+    }
+
+    #[test]
+    fn streams_messages_across_reconnections() {
+        let mut server = Server::bind("127.0.0.1:0").unwrap();
+        let address = server.local_addr().unwrap();
+        let (pongs, pong_received) = mpsc::channel();
+        thread::spawn(move || {
+            let mut client = server.accept().ok().unwrap().accept().ok().unwrap();
+            client
+                .send_message(&OwnedMessage::Text(r#"{"type": "ping", "ts": 1500000000000}"#.to_string()))
+                .unwrap();
+            client.send_message(&OwnedMessage::Ping(b"hb".to_vec())).unwrap();
+            pongs.send(client.recv_message().unwrap()).unwrap();
+            client.send_message(&OwnedMessage::Text(message("m1"))).unwrap();
+            client.send_message(&OwnedMessage::Close(None)).unwrap();
+
+            let mut client = server.accept().ok().unwrap().accept().ok().unwrap();
+            client.send_message(&OwnedMessage::Text(message("m2"))).unwrap();
+            let _ = client.recv_message();
+        });
+
+        let token = oauth2::Token::from_access_token("token".to_string());
+        let messages = live_from(Url::parse(&format!("ws://{}/live", address)).unwrap(),
+                                 token,
+                                 LiveFilter::Devices(vec!["d1".to_string()]),
+                                 Duration::from_millis(10))
+                .take(2)
+                .collect()
+                .wait()
+                .unwrap();
+        assert_eq!(messages.iter().map(|message| message.mid.clone()).collect::<Vec<String>>(),
+                   vec!["m1".to_string(), "m2".to_string()]);
+        assert_eq!(pong_received.recv().unwrap(), OwnedMessage::Pong(b"hb".to_vec()));
+    }
+
+    #[test]
+    fn closes_connection_once_dropped() {
+        let mut server = Server::bind("127.0.0.1:0").unwrap();
+        let address = server.local_addr().unwrap();
+        let (closes, close_received) = mpsc::channel();
+        thread::spawn(move || {
+            let mut client = server.accept().ok().unwrap().accept().ok().unwrap();
+            client.send_message(&OwnedMessage::Text(message("m1"))).unwrap();
+            thread::sleep(Duration::from_millis(100));
+            client
+                .send_message(&OwnedMessage::Text(r#"{"type": "ping", "ts": 1500000000000}"#.to_string()))
+                .unwrap();
+            closes.send(client.recv_message().unwrap()).unwrap();
+        });
+
+        let token = oauth2::Token::from_access_token("token".to_string());
+        let messages = live_from(Url::parse(&format!("ws://{}/live", address)).unwrap(),
+                                 token,
+                                 LiveFilter::Devices(vec!["d1".to_string()]),
+                                 Duration::from_millis(10))
+                .take(1)
+                .collect()
+                .wait()
+                .unwrap();
+        assert_eq!(messages.len(), 1);
+        assert_eq!(close_received.recv_timeout(Duration::from_secs(5)).unwrap(),
+                   OwnedMessage::Close(None));
+    }
+
+    #[test]
+    fn ends_with_rejected_subscription() {
+        let mut server = Server::bind("127.0.0.1:0").unwrap();
+        let address = server.local_addr().unwrap();
+        let (handshakes, handshake_received) = mpsc::channel();
+        thread::spawn(move || {
+            let upgrade = server.accept().ok().unwrap();
+            handshakes
+                .send((upgrade.request.subject.1.to_string(),
+                       upgrade.request.headers.get::<Authorization<Bearer>>().map(|authorization| authorization.token.clone())))
+                .unwrap();
+            let mut client = upgrade.accept().ok().unwrap();
+            client
+                .send_message(&OwnedMessage::Text(r#"{"error": {"code": 401, "message": "Please provide a valid authorization header"}}"#
+                                                      .to_string()))
+                .unwrap();
+        });
+
+        let token = oauth2::Token::from_access_token("expired".to_string());
+        let mut messages = live_from(Url::parse(&format!("ws://{}/live", address)).unwrap(),
+                                     token,
+                                     LiveFilter::User("u1".to_string()),
+                                     Duration::from_millis(10))
+                .wait();
+        match messages.next() {
+            Some(Err(AkcClientError::AkcError(401, _))) => (),
+            other => panic!("unexpected item {:?}", other),
+        }
+        let (uri, token) = handshake_received.recv().unwrap();
+        assert!(!uri.contains("expired"));
+        assert_eq!(token, Some("expired".to_string()));
+    }
+}
@@ -0,0 +1,229 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use hyper::Url;
+use futures::future::*;
+use serde_json;
+use transient_hashmap::TransientHashMap;
+
+use oauth2;
+
+use Akc;
+use error::AkcClientError;
+use helpers;
+use message::Action;
+use snapshot::FieldValue;
+
+data_wrapper!(DataManifest, ManifestProperties);
+
+lazy_static! {
+    /// Manifests of device types by id, kept for a few hours as they seldom change.
+    static ref MANIFEST_CACHE: Mutex<TransientHashMap<String, Manifest>> = Mutex::new(TransientHashMap::new(60 * 60 * 6));
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct ManifestProperties {
+    properties: Manifest,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct Manifest {
+    pub fields: HashMap<String, Box<FieldDescription>>,
+    #[serde(default)]
+    pub actions: HashMap<String, ActionDescription>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct ActionDescription {
+    pub description: Option<String>,
+    #[serde(default)]
+    pub parameters: HashMap<String, serde_json::Value>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum FieldDescription {
+    Field {
+        #[serde(rename = "type")]
+        field_type: String,
+        unit: Option<String>,
+        description: Option<String>,
+        #[serde(rename = "isCollection", default)]
+        is_collection: bool,
+    },
+    Group(HashMap<String, Box<FieldDescription>>),
+}
+
+impl Manifest {
+    /// Finds the description of a field from its path, groups first.
+    pub fn field(&self, path: &[String]) -> Option<&FieldDescription> {
+        let (first, rest) = match path.split_first() {
+            Some(split) => split,
+            None => return None,
+        };
+        let mut description = match self.fields.get(first) {
+            Some(description) => &**description,
+            None => return None,
+        };
+        for name in rest {
+            description = match *description {
+                FieldDescription::Group(ref subfields) => {
+                    match subfields.get(name) {
+                        Some(subfield) => &**subfield,
+                        None => return None,
+                    }
+                }
+                FieldDescription::Field { .. } => return None,
+            };
+        }
+        Some(description)
+    }
+
+    pub fn unit(&self, path: &[String]) -> Option<String> {
+        match self.field(path) {
+            Some(&FieldDescription::Field { ref unit, .. }) => unit.clone(),
+            _ => None,
+        }
+    }
+
+    /// Paths of every field, sorted.
+    pub fn field_paths(&self) -> Vec<Vec<String>> {
+        let mut paths = vec![];
+        collect_field_paths(&self.fields, vec![], &mut paths);
+        paths.sort();
+        paths
+    }
+
+    pub fn supports_action(&self, name: &str) -> bool {
+        self.actions.contains_key(name)
+    }
+
+    /// The action setting a field to `value`: `set<Field>` if declared, otherwise the only action taking the field
+    /// as parameter. `None` when the device type declares no way to change the field.
+    pub fn setter_action(&self, field_name: &str, value: &FieldValue) -> Option<Action> {
+        let conventional = setter_action_name(field_name);
+        let name = if self.supports_action(&conventional) {
+            conventional
+        } else {
+            let mut taking_field = self.actions
+                .iter()
+                .filter(|&(_, action)| action.parameters.contains_key(field_name))
+                .map(|(name, _)| name.clone())
+                .collect::<Vec<String>>();
+            if taking_field.len() != 1 {
+                return None;
+            }
+            taking_field.remove(0)
+        };
+        let mut parameters = serde_json::Map::new();
+        parameters.insert(field_name.to_string(), serde_json::to_value(value).unwrap());
+        Some(Action {
+                 name: name,
+                 parameters: serde_json::Value::Object(parameters),
+             })
+    }
+}
+
+fn setter_action_name(field_name: &str) -> String {
+    let mut chars = field_name.chars();
+    match chars.next() {
+        Some(first) => format!("set{}{}", first.to_uppercase().collect::<String>(), chars.as_str()),
+        None => "set".to_string(),
+    }
+}
+
+fn collect_field_paths(fields: &HashMap<String, Box<FieldDescription>>, path: Vec<String>, paths: &mut Vec<Vec<String>>) {
+    for (name, description) in fields.iter() {
+        let mut field_path = path.clone();
+        field_path.push(name.to_owned());
+        match **description {
+            FieldDescription::Field { .. } => paths.push(field_path),
+            FieldDescription::Group(ref subfields) => collect_field_paths(subfields, field_path, paths),
+        }
+    }
+}
+
+impl Akc {
+    pub fn manifest(token: oauth2::Token, dtid: &str) -> Box<Future<Item = Manifest, Error = AkcClientError>> {
+        let url = Url::parse(&format!("{}/devicetypes/{}/manifests/latest/properties", Self::base_url(), dtid)).unwrap();
+
+        Self::get::<DataManifest>(token, url)
+            .map(|properties| properties.properties)
+            .boxed()
+    }
+
+    /// The latest manifest of `dtid`, fetched again only once the cached one is a few hours old.
+    pub fn manifest_cached(token: oauth2::Token, dtid: &str) -> Box<Future<Item = Manifest, Error = AkcClientError>> {
+        let cached = {
+            let mut cache = MANIFEST_CACHE.lock().unwrap();
+            cache.prune();
+            cache.get(&dtid.to_string()).cloned()
+        };
+        if let Some(manifest) = cached {
+            return ok(manifest).boxed();
+        }
+        let dtid = dtid.to_string();
+        Self::manifest(token, &dtid)
+            .map(move |manifest| {
+                     MANIFEST_CACHE
+                         .lock()
+                         .unwrap()
+                         .insert(dtid, manifest.clone());
+                     manifest
+                 })
+            .boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json;
+
+    use super::*;
+
+    #[test]
+    fn finds_unit_of_nested_field() {
+        let manifest: ManifestProperties = serde_json::from_str(r#"{"properties": {"fields": {
+            "temperature": {"type": "CUSTOM", "unit": "°C"},
+            "state": {"on": {"type": "CUSTOM"}}
+        }, "actions": {"setOn": {"parameters": {}}}}}"#)
+                .unwrap();
+        let manifest = manifest.properties;
+        assert_eq!(manifest.unit(&["temperature".to_string()]), Some("°C".to_string()));
+        assert_eq!(manifest.unit(&["state".to_string(), "on".to_string()]), None);
+        assert!(manifest.field(&["state".to_string(), "on".to_string()]).is_some());
+        assert!(manifest.field(&["state".to_string(), "off".to_string()]).is_none());
+    }
+
+    #[test]
+    fn lists_fields_and_actions() {
+        let manifest: ManifestProperties = serde_json::from_str(r#"{"properties": {"fields": {
+            "temperature": {"type": "CUSTOM", "unit": "°C"},
+            "state": {"on": {"type": "CUSTOM", "isCollection": false}}
+        }, "actions": {"setOn": {"parameters": {}}}}}"#)
+                .unwrap();
+        let manifest = manifest.properties;
+        assert_eq!(manifest.field_paths(),
+                   vec![vec!["state".to_string(), "on".to_string()], vec!["temperature".to_string()]]);
+        assert!(manifest.supports_action("setOn"));
+        assert!(!manifest.supports_action("setTemperature"));
+    }
+
+    #[test]
+    fn routes_values_to_declared_actions() {
+        let manifest: ManifestProperties = serde_json::from_str(r#"{"properties": {"fields": {
+            "on": {"type": "CUSTOM"},
+            "level": {"type": "CUSTOM"},
+            "temperature": {"type": "CUSTOM", "unit": "°C"}
+        }, "actions": {"setOn": {"parameters": {}}, "dim": {"parameters": {"level": {"type": "CUSTOM"}}}}}}"#)
+                .unwrap();
+        let manifest = manifest.properties;
+        let action = manifest.setter_action("on", &FieldValue::Boolean(true)).unwrap();
+        assert_eq!(action.name, "setOn");
+        assert_eq!(action.parameters, serde_json::from_str::<serde_json::Value>(r#"{"on": true}"#).unwrap());
+        let action = manifest.setter_action("level", &FieldValue::Int(30)).unwrap();
+        assert_eq!(action.name, "dim");
+        assert_eq!(action.parameters, serde_json::from_str::<serde_json::Value>(r#"{"level": 30}"#).unwrap());
+        assert!(manifest.setter_action("temperature", &FieldValue::Float(21.0)).is_none());
+    }
+}
@@ -0,0 +1,258 @@
+use hyper::Url;
+use futures::future::*;
+use serde_json;
+
+use oauth2;
+
+use Akc;
+use error::AkcClientError;
+use helpers;
+
+data_wrapper!(DataMessageId, MessageId);
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+struct DataMessages {
+    data: Vec<NormalizedMessage>,
+    next: Option<String>,
+}
+impl helpers::DataWrapper for DataMessages {
+    type Data = Vec<NormalizedMessage>;
+    fn data(self: Self) -> Self::Data {
+        self.data
+    }
+}
+impl helpers::CursorWrapper for DataMessages {
+    fn next(self: &Self) -> Option<String> {
+        match self.next {
+            Some(ref next) if !next.is_empty() => Some(next.clone()),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct NormalizedMessage {
+    pub mid: String,
+    pub sdid: String,
+    /// Time of the message, in milliseconds since epoch.
+    pub ts: u64,
+    pub data: serde_json::Value,
+}
+impl NormalizedMessage {
+    /// Value of a field from its path, groups first.
+    pub fn field(&self, path: &[String]) -> Option<&serde_json::Value> {
+        path.iter().fold(Some(&self.data), |data, name| data.and_then(|data| data.get(name)))
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+struct DataAggregates {
+    data: Vec<Aggregate>,
+}
+impl helpers::DataWrapper for DataAggregates {
+    type Data = Vec<Aggregate>;
+    fn data(self: Self) -> Self::Data {
+        self.data
+    }
+}
+
+/// Statistics over the values of a field.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Default)]
+pub struct Aggregate {
+    pub count: u64,
+    #[serde(default)]
+    pub min: f64,
+    #[serde(default)]
+    pub max: f64,
+    #[serde(default)]
+    pub mean: f64,
+    #[serde(default)]
+    pub sum: f64,
+    #[serde(default)]
+    pub variance: f64,
+    /// Start of the interval, in milliseconds since epoch, for histograms.
+    pub ts: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Interval {
+    Minute,
+    Hour,
+    Day,
+    Month,
+    Year,
+}
+impl Interval {
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            Interval::Minute => "minute",
+            Interval::Hour => "hour",
+            Interval::Day => "day",
+            Interval::Month => "month",
+            Interval::Year => "year",
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct MessageId {
+    pub mid: String,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct Action {
+    pub name: String,
+    pub parameters: serde_json::Value,
+}
+
+#[derive(Serialize, Debug, Clone)]
+struct Actions {
+    actions: Vec<Action>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+struct OutgoingMessage {
+    sdid: String,
+    #[serde(rename = "type")]
+    message_type: String,
+    data: serde_json::Value,
+}
+
+#[derive(Serialize, Debug, Clone)]
+struct OutgoingAction {
+    ddid: String,
+    #[serde(rename = "type")]
+    message_type: String,
+    data: Actions,
+}
+
+impl Akc {
+    pub fn send_message(token: oauth2::Token,
+                        sdid: &str,
+                        data: serde_json::Value)
+                        -> Box<Future<Item = MessageId, Error = AkcClientError>> {
+        let url = Url::parse(&format!("{}/messages", Self::base_url::<'static>())).unwrap();
+        let message = OutgoingMessage {
+            sdid: sdid.to_string(),
+            message_type: "message".to_string(),
+            data,
+        };
+
+        Self::post::<DataMessageId>(token, url, serde_json::to_string(&message).unwrap())
+    }
+
+    /// Messages sent by `sdid` between `start_date` and `end_date` (in milliseconds since epoch), oldest first.
+    /// Only messages with `field` are returned when it is set. Beyond `max_pages` pages, the most recent messages are kept.
+    pub fn messages(token: oauth2::Token,
+                    sdid: &str,
+                    start_date: u64,
+                    end_date: u64,
+                    field: Option<&str>,
+                    max_pages: u64)
+                    -> Box<Future<Item = Vec<NormalizedMessage>, Error = AkcClientError>> {
+        let url = Url::parse(&format!("{}/messages", Self::base_url::<'static>())).unwrap();
+        let mut params = vec![("sdid".to_string(), sdid.to_string()),
+                              ("startDate".to_string(), start_date.to_string()),
+                              ("endDate".to_string(), end_date.to_string()),
+                              ("count".to_string(), "100".to_string()),
+                              ("order".to_string(), "desc".to_string())];
+        if let Some(field) = field {
+            params.push(("fieldPresence".to_string(), field.to_string()));
+        }
+
+        Self::get_all_pages_with_cursor::<DataMessages>(token, url, params, max_pages)
+            .map(|mut messages| {
+                     messages.reverse();
+                     messages
+                 })
+            .boxed()
+    }
+
+    /// Statistics of the numeric `field` of `sdid` between `start_date` and `end_date`, `None` without any value.
+    pub fn aggregates(token: oauth2::Token,
+                      sdid: &str,
+                      start_date: u64,
+                      end_date: u64,
+                      field: &str)
+                      -> Box<Future<Item = Option<Aggregate>, Error = AkcClientError>> {
+        let url = Url::parse(&format!("{}/messages/analytics/aggregates", Self::base_url::<'static>())).unwrap();
+        let params = vec![("sdid".to_string(), sdid.to_string()),
+                          ("startDate".to_string(), start_date.to_string()),
+                          ("endDate".to_string(), end_date.to_string()),
+                          ("field".to_string(), field.to_string())];
+
+        Self::get_with_params::<DataAggregates>(token, url, params)
+            .map(|aggregates| aggregates.into_iter().find(|aggregate| aggregate.count > 0))
+            .boxed()
+    }
+
+    /// Statistics of the numeric `field` of `sdid` for each `interval` between `start_date` and `end_date`.
+    pub fn histogram(token: oauth2::Token,
+                     sdid: &str,
+                     start_date: u64,
+                     end_date: u64,
+                     field: &str,
+                     interval: Interval)
+                     -> Box<Future<Item = Vec<Aggregate>, Error = AkcClientError>> {
+        let url = Url::parse(&format!("{}/messages/analytics/histogram", Self::base_url::<'static>())).unwrap();
+        let params = vec![("sdid".to_string(), sdid.to_string()),
+                          ("startDate".to_string(), start_date.to_string()),
+                          ("endDate".to_string(), end_date.to_string()),
+                          ("field".to_string(), field.to_string()),
+                          ("interval".to_string(), interval.as_str().to_string())];
+
+        Self::get_with_params::<DataAggregates>(token, url, params)
+    }
+
+    pub fn send_action(token: oauth2::Token, ddid: &str, action: Action) -> Box<Future<Item = MessageId, Error = AkcClientError>> {
+        let url = Url::parse(&format!("{}/messages", Self::base_url::<'static>())).unwrap();
+        let message = OutgoingAction {
+            ddid: ddid.to_string(),
+            message_type: "action".to_string(),
+            data: Actions { actions: vec![action] },
+        };
+
+        Self::post::<DataMessageId>(token, url, serde_json::to_string(&message).unwrap())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json;
+
+    use helpers::{CursorWrapper, DataWrapper};
+
+    use super::*;
+
+    #[test]
+    fn reads_page_of_messages() {
+        let page: DataMessages = serde_json::from_str(r#"{"sdid": "d1", "count": 1, "size": 1, "next": "abc", "data": [
+            {"mid": "m1", "sdid": "d1", "ts": 1500000000000, "data": {"state": {"temperature": 21.5}}}
+        ]}"#)
+                .unwrap();
+        assert_eq!(page.next(), Some("abc".to_string()));
+        let messages = page.data();
+        assert_eq!(messages[0].field(&["state".to_string(), "temperature".to_string()]),
+                   Some(&serde_json::Value::from(21.5)));
+        assert_eq!(messages[0].field(&["humidity".to_string()]), None);
+    }
+
+    #[test]
+    fn reads_histogram() {
+        let histogram: DataAggregates = serde_json::from_str(r#"{"sdid": "d1", "field": "power", "interval": "day", "size": 2, "data": [
+            {"count": 3, "min": 1.0, "max": 3.0, "mean": 2.0, "sum": 6.0, "variance": 0.66, "ts": 1499990400000},
+            {"count": 0, "ts": 1500076800000}
+        ]}"#)
+                .unwrap();
+        let buckets = histogram.data();
+        assert_eq!(buckets[0].sum, 6.0);
+        assert_eq!(buckets[0].ts, Some(1_499_990_400_000));
+        assert_eq!(buckets[1].count, 0);
+    }
+
+    #[test]
+    fn last_page_has_no_cursor() {
+        let page: DataMessages = serde_json::from_str(r#"{"next": "", "data": []}"#).unwrap();
+        assert_eq!(page.next(), None);
+    }
+}
@@ -34,6 +34,88 @@ pub enum FieldData {
     Group(HashMap<String, Box<FieldData>>),
 }
 
+impl FieldData {
+    /// Finds a field from its path, groups first.
+    pub fn get(&self, path: &[String]) -> Option<&FieldData> {
+        path.iter().fold(Some(self), |data, name| match data {
+            Some(&FieldData::Group(ref subfields)) => subfields.get(name).map(|subfield| &**subfield),
+            _ => None,
+        })
+    }
+
+    /// Every field under this one, with its path from here.
+    pub fn fields(&self) -> Vec<(Vec<String>, Option<u64>, &FieldValue)> {
+        match *self {
+            FieldData::Field { ts, ref value } => vec![(vec![], ts, value)],
+            FieldData::Group(ref subfields) => {
+                subfields
+                    .iter()
+                    .flat_map(|(name, subfield)| {
+                        subfield
+                            .fields()
+                            .into_iter()
+                            .map(move |(path, ts, value)| {
+                                     let mut full_path = vec![name.clone()];
+                                     full_path.extend(path);
+                                     (full_path, ts, value)
+                                 })
+                    })
+                    .collect()
+            }
+        }
+    }
+
+    /// Paths of the fields named by `indication`, shortest first. See `path_matches`.
+    pub fn find(&self, indication: &str) -> Vec<Vec<String>> {
+        let mut paths = self.fields()
+            .into_iter()
+            .map(|(path, _, _)| path)
+            .filter(|path| path_matches(path, indication))
+            .collect::<Vec<Vec<String>>>();
+        paths.sort_by(|a, b| a.len().cmp(&b.len()).then_with(|| a.cmp(b)));
+        paths
+    }
+}
+
+fn normalize(name: &str) -> String {
+    name.chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(|c| c.to_lowercase())
+        .collect()
+}
+
+/// Whether `indication`, as "gps lat", "gps.lat" or "lat", names the field at `path`: its last words name the
+/// field, and the words before name some of its groups, in order. Case and separators are ignored, so
+/// "current temperature" names `currentTemperature`.
+pub fn path_matches(path: &[String], indication: &str) -> bool {
+    let words = indication
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| word.to_lowercase())
+        .collect::<Vec<String>>();
+    let names = path.iter().map(|name| normalize(name)).collect::<Vec<String>>();
+    let (field, groups) = match names.split_last() {
+        Some(split) => split,
+        None => return false,
+    };
+    (1..words.len() + 1).any(|count| {
+                                 let (before, last) = words.split_at(words.len() - count);
+                                 last.concat() == *field && groups_match(before, groups)
+                             })
+}
+
+fn groups_match(words: &[String], groups: &[String]) -> bool {
+    if words.is_empty() {
+        return true;
+    }
+    let (group, others) = match groups.split_first() {
+        Some(split) => split,
+        None => return false,
+    };
+    (1..words.len() + 1).any(|count| words[..count].concat() == *group && groups_match(&words[count..], others)) ||
+    groups_match(words, others)
+}
+
 #[derive(Deserialize, Serialize, Debug, Clone)]
 #[serde(untagged)]
 pub enum FieldValue {
@@ -52,6 +134,40 @@ impl fmt::Display for FieldValue {
         }
     }
 }
+impl FieldValue {
+    pub fn type_name(&self) -> &'static str {
+        match *self {
+            FieldValue::Float(_) => "number",
+            FieldValue::Int(_) => "integer",
+            FieldValue::String(_) => "text",
+            FieldValue::Boolean(_) => "boolean",
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match *self {
+            FieldValue::Float(f) => Some(f),
+            FieldValue::Int(i) => Some(i as f64),
+            _ => None,
+        }
+    }
+
+    pub fn parse_like(&self, raw: &str) -> Option<FieldValue> {
+        let raw = raw.trim();
+        match *self {
+            FieldValue::Float(_) => raw.parse::<f64>().ok().map(FieldValue::Float),
+            FieldValue::Int(_) => raw.parse::<i64>().ok().map(FieldValue::Int),
+            FieldValue::String(_) => Some(FieldValue::String(raw.trim_matches('"').to_string())),
+            FieldValue::Boolean(_) => {
+                match raw.to_lowercase().as_ref() {
+                    "true" | "on" | "yes" | "1" => Some(FieldValue::Boolean(true)),
+                    "false" | "off" | "no" | "0" => Some(FieldValue::Boolean(false)),
+                    _ => None,
+                }
+            }
+        }
+    }
+}
 
 impl Akc {
     pub fn snapshots(token: oauth2::Token, sdid: Vec<String>) -> Box<Future<Item = Vec<Snapshot>, Error = AkcClientError>> {
@@ -60,3 +176,42 @@ impl Akc {
         Self::get_with_params::<DataSnapshot>(token, url, vec![("sdids".to_string(), sdid.join(","))])
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use serde_json;
+
+    use super::*;
+
+    #[test]
+    fn finds_nested_field() {
+        let snapshot: Snapshot = serde_json::from_str(r#"{"sdid": "d1", "data": {
+            "temperature": {"ts": 1500000000000, "value": -12.5},
+            "state": {"door": {"ts": 1500000000000, "value": "open"}}
+        }}"#)
+                .unwrap();
+        match snapshot.data.get(&["temperature".to_string()]) {
+            Some(&FieldData::Field { ref value, .. }) => assert_eq!(value.as_f64(), Some(-12.5)),
+            other => panic!("unexpected field {:?}", other),
+        }
+        assert!(snapshot.data.get(&["state".to_string(), "door".to_string()]).is_some());
+        assert!(snapshot.data.get(&["state".to_string(), "window".to_string()]).is_none());
+    }
+
+    #[test]
+    fn finds_fields_by_path() {
+        let snapshot: Snapshot = serde_json::from_str(r#"{"sdid": "d1", "data": {
+            "currentTemperature": {"ts": 1500000000000, "value": 21.5},
+            "location": {"lat": {"ts": 1500000000000, "value": 48.85}},
+            "tracker": {"gps": {"lat": {"ts": 1500000000000, "value": 48.86}}}
+        }}"#)
+                .unwrap();
+        let path = |path: &str| path.split('.').map(|name| name.to_string()).collect::<Vec<String>>();
+        assert_eq!(snapshot.data.find("lat"), vec![path("location.lat"), path("tracker.gps.lat")]);
+        assert_eq!(snapshot.data.find("gps lat"), vec![path("tracker.gps.lat")]);
+        assert_eq!(snapshot.data.find("tracker.gps.lat"), vec![path("tracker.gps.lat")]);
+        assert_eq!(snapshot.data.find("Current temperature"), vec![path("currentTemperature")]);
+        assert!(snapshot.data.find("gps temperature").is_empty());
+        assert_eq!(snapshot.data.fields().len(), 3);
+    }
+}
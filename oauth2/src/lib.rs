@@ -18,17 +18,18 @@ use hyper::header::{Headers, Authorization, Basic, Bearer, ContentType};
 use hyper::mime::{Mime, TopLevel, SubLevel};
 use hyper::status::StatusCode;
 
-#[derive(Default, Debug, Clone)]
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
 pub struct Token {
     access_token: String,
     refresh_token: Option<String>,
+    #[serde(with = "seconds_since_epoch")]
     expires_at: Option<SystemTime>,
 }
 impl Token {
     fn new(tokens: ExternalToken) -> Token {
         Token {
             access_token: tokens.access_token,
-            refresh_token: Some(tokens.refresh_token),
+            refresh_token: tokens.refresh_token,
             expires_at: Some(SystemTime::now() + Duration::from_secs(tokens.expires_in)),
         }
     }
@@ -43,20 +44,56 @@ impl Token {
         &self.access_token
     }
 
+    pub fn refresh_token(self: &Self) -> Option<&str> {
+        self.refresh_token.as_ref().map(|refresh_token| refresh_token.as_str())
+    }
+
+    /// A token without a known expiration date is never considered expired.
+    pub fn is_expired(self: &Self) -> bool {
+        self.expires_within(Duration::from_secs(0))
+    }
+
+    pub fn expires_within(self: &Self, delay: Duration) -> bool {
+        match self.expires_at {
+            Some(expires_at) => expires_at <= SystemTime::now() + delay,
+            None => false,
+        }
+    }
+
     pub fn bearer(self: &Self) -> hyper::header::Authorization<hyper::header::Bearer> {
         Authorization(Bearer { token: self.access_token.to_owned() })
     }
 }
 
+/// Stored tokens keep their expiration as a number of seconds, which doesn't depend on how serde represents a `SystemTime`.
+mod seconds_since_epoch {
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S>(time: &Option<SystemTime>, serializer: S) -> Result<S::Ok, S::Error>
+        where S: Serializer
+    {
+        time.map(|time| time.duration_since(UNIX_EPOCH).map(|since| since.as_secs()).unwrap_or(0))
+            .serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Option<SystemTime>, D::Error>
+        where D: Deserializer<'de>
+    {
+        Option::<u64>::deserialize(deserializer).map(|seconds| seconds.map(|seconds| UNIX_EPOCH + Duration::from_secs(seconds)))
+    }
+}
+
 #[derive(Deserialize, Serialize, Debug, Clone)]
 struct ExternalToken {
     access_token: String,
-    refresh_token: String,
+    refresh_token: Option<String>,
     token_type: String,
     expires_in: u64,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, PartialEq)]
 #[serde(rename_all = "snake_case")]
 enum Oauth2ErrorMessage {
     InvalidRequest,
@@ -83,6 +120,18 @@ pub struct Oauth2Error {
 pub enum Error {
     CommunicationError(String),
     Oauth2Error(Oauth2Error),
+    /// A 401 without an OAuth2 error in its body.
+    Unauthorized,
+}
+impl Error {
+    /// Whether the server refused the client credentials themselves, rather than the request.
+    pub fn is_invalid_client(&self) -> bool {
+        match *self {
+            Error::Oauth2Error(ref err) => err.error == Oauth2ErrorMessage::InvalidClient,
+            Error::Unauthorized => true,
+            Error::CommunicationError(_) => false,
+        }
+    }
 }
 impl From<hyper::Error> for Error {
     fn from(err: hyper::Error) -> Error {
@@ -111,6 +160,29 @@ impl fmt::Display for AuthorizationCode {
     }
 }
 
+pub struct RefreshToken {
+    pub refresh_token: String,
+}
+impl Grant for RefreshToken {}
+impl fmt::Display for RefreshToken {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "grant_type=refresh_token&refresh_token={}", self.refresh_token)
+    }
+}
+
+pub struct ClientCredentials {
+    pub scope: Option<String>,
+}
+impl Grant for ClientCredentials {}
+impl fmt::Display for ClientCredentials {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.scope {
+            Some(ref scope) => write!(f, "grant_type=client_credentials&scope={}", scope),
+            None => write!(f, "grant_type=client_credentials"),
+        }
+    }
+}
+
 pub struct Oauth2 {
     app_id: String,
     app_secret: String,
@@ -150,19 +222,75 @@ impl Oauth2 {
                     Err(error) => Err(error)?,
                 }
             }
-            _ => {
+            status => {
                 let token: Result<Oauth2Error, serde_json::Error> = serde_json::from_reader(response);
                 match token {
                     Ok(error) => Err(error)?,
+                    Err(_) if status == StatusCode::Unauthorized => Err(Error::Unauthorized),
                     Err(error) => Err(error)?,
                 }
             }
         }
     }
+    /// Servers may not rotate the refresh token, in which case the current one is kept.
+    pub fn refresh(self: Self, token: &Token) -> Result<Token, Error> {
+        let refresh_token = match token.refresh_token {
+            Some(ref refresh_token) => refresh_token.clone(),
+            None => return Err(Error::CommunicationError("no refresh token available".to_string())),
+        };
+        let mut refreshed = self.exchange_token(RefreshToken { refresh_token: refresh_token.clone() })?;
+        if refreshed.refresh_token.is_none() {
+            refreshed.refresh_token = Some(refresh_token);
+        }
+        Ok(refreshed)
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use serde_json;
+
+    use super::{Token, Error, Oauth2Error};
+
     #[test]
     fn it_works() {}
+
+    #[test]
+    fn recognizes_invalid_client() {
+        let error = |body: &str| Error::from(serde_json::from_str::<Oauth2Error>(body).unwrap());
+        assert!(error(r#"{"error": "invalid_client"}"#).is_invalid_client());
+        assert!(!error(r#"{"error": "invalid_scope", "error_description": "no such scope"}"#).is_invalid_client());
+        assert!(Error::Unauthorized.is_invalid_client());
+        assert!(!Error::CommunicationError("timeout".to_string()).is_invalid_client());
+    }
+
+    #[test]
+    fn token_expiration() {
+        let token = Token {
+            access_token: "token".to_string(),
+            refresh_token: None,
+            expires_at: Some(SystemTime::now() + Duration::from_secs(30)),
+        };
+        assert!(!token.is_expired());
+        assert!(token.expires_within(Duration::from_secs(60)));
+        assert!(!Token::from_access_token("token".to_string()).expires_within(Duration::from_secs(60)));
+    }
+
+    #[test]
+    fn token_roundtrip() {
+        let token = Token {
+            access_token: "token".to_string(),
+            refresh_token: Some("refresh".to_string()),
+            expires_at: Some(SystemTime::now() + Duration::from_secs(3600)),
+        };
+        let stored = serde_json::to_string(&token).unwrap();
+        let loaded = serde_json::from_str::<Token>(&stored).unwrap();
+        assert_eq!(loaded.refresh_token(), Some("refresh"));
+        assert!(!loaded.expires_within(Duration::from_secs(3500)));
+        assert!(loaded.expires_within(Duration::from_secs(3700)));
+        let without_expiration = serde_json::to_string(&Token::from_access_token("token".to_string())).unwrap();
+        assert!(!serde_json::from_str::<Token>(&without_expiration).unwrap().is_expired());
+    }
 }
@@ -0,0 +1,190 @@
+use std::sync::Mutex;
+use std::time::Duration;
+
+use hyper;
+use hyper::Url;
+use hyper::header::{Headers, ContentType};
+use hyper::status::StatusCode;
+use futures::future::*;
+use serde_json;
+use transient_hashmap::TransientHashMap;
+
+use oauth2;
+use future_request;
+
+use CONFIGURATION;
+
+use clients::hipchat::capabilities::ServerCapabilities;
+use clients::hipchat::installation::Installation;
+use clients::hipchat::message::RoomNotification;
+
+#[derive(Debug, Clone)]
+pub struct HipchatApiError {
+    msg: String,
+}
+impl From<hyper::Error> for HipchatApiError {
+    fn from(err: hyper::Error) -> HipchatApiError {
+        HipchatApiError { msg: format!("couldn't contact HipChat: {:?}", err) }
+    }
+}
+impl From<serde_json::Error> for HipchatApiError {
+    fn from(err: serde_json::Error) -> HipchatApiError {
+        HipchatApiError { msg: format!("error parsing json: {:?}", err) }
+    }
+}
+impl From<oauth2::Error> for HipchatApiError {
+    fn from(err: oauth2::Error) -> HipchatApiError {
+        HipchatApiError { msg: format!("couldn't get a HipChat token: {:?}", err) }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct ApiAccess {
+    token: oauth2::Token,
+    api_url: String,
+}
+
+lazy_static! {
+    /// HipChat tokens last an hour, older ones are dropped even if their installation never asks again.
+    static ref TOKEN_CACHE: Mutex<TransientHashMap<String, ApiAccess>> = Mutex::new(TransientHashMap::new(60 * 60));
+}
+
+/// Whether `url` points to one of the configured HipChat hosts over https.
+pub fn is_hipchat_url(url: &str) -> bool {
+    match Url::parse(url) {
+        Ok(url) => {
+            url.scheme() == "https" &&
+            url.host_str()
+                .map(|host| CONFIGURATION.hipchat_hosts.iter().any(|allowed| *allowed == host.to_lowercase()))
+                .unwrap_or(false)
+        }
+        Err(_) => false,
+    }
+}
+
+pub fn fetch_capabilities(capabilities_url: &str) -> Box<Future<Item = ServerCapabilities, Error = HipchatApiError> + Send> {
+    if !is_hipchat_url(capabilities_url) {
+        return err(HipchatApiError { msg: format!("{} is not a HipChat url", capabilities_url) }).boxed();
+    }
+    let url = match Url::parse(capabilities_url) {
+        Ok(url) => url,
+        Err(error) => return err(HipchatApiError { msg: format!("invalid capabilities url: {:?}", error) }).boxed(),
+    };
+    future_request::get_async::<HipchatApiError>(url, Headers::new())
+        .and_then(|response| match StatusCode::from_u16(response.status_raw().0) {
+                      StatusCode::Ok => {
+                          match serde_json::from_reader(response) {
+                              Ok(capabilities) => Ok(capabilities),
+                              Err(error) => Err(error)?,
+                          }
+                      }
+                      status => Err(HipchatApiError { msg: format!("couldn't get capabilities: {}", status) }),
+                  })
+        .boxed()
+}
+
+fn api_access(installation: &Installation) -> Box<Future<Item = ApiAccess, Error = HipchatApiError> + Send> {
+    let cached = {
+        let mut lock = TOKEN_CACHE.lock().unwrap();
+        lock.prune();
+        lock.get(&installation.oauth_id).cloned()
+    };
+    match cached {
+        Some(ref access) if !access.token.expires_within(Duration::from_secs(60)) => return ok(access.clone()).boxed(),
+        _ => (),
+    }
+    let installation = installation.clone();
+    endpoints(&installation)
+        .and_then(move |(token_url, api_url)| {
+            let access = ApiAccess {
+                token: request_token(&installation, &token_url)?,
+                api_url,
+            };
+            TOKEN_CACHE
+                .lock()
+                .unwrap()
+                .insert(installation.oauth_id.clone(), access.clone());
+            Ok(access)
+        })
+        .boxed()
+}
+
+/// Validates that the capabilities document comes from a HipChat server and records its endpoints.
+pub fn register_endpoints(mut installation: Installation) -> Box<Future<Item = Installation, Error = HipchatApiError> + Send> {
+    fetch_capabilities(&installation.capabilities_url)
+        .and_then(move |capabilities| {
+            if capabilities.key != "hipchat" {
+                return Err(HipchatApiError { msg: format!("{} is not a HipChat server", capabilities.name) });
+            }
+            if !is_hipchat_url(&capabilities.capabilities.oauth2_provider.token_url) || !is_hipchat_url(&capabilities.links.api) {
+                return Err(HipchatApiError { msg: "endpoint outside of HipChat in capabilities".to_string() });
+            }
+            installation.token_url = Some(capabilities.capabilities.oauth2_provider.token_url);
+            installation.api_url = Some(capabilities.links.api.trim_right_matches('/').to_string());
+            Ok(installation)
+        })
+        .boxed()
+}
+
+/// Installations registered before their endpoints were recorded fall back on the capabilities document.
+fn endpoints(installation: &Installation) -> Box<Future<Item = (String, String), Error = HipchatApiError> + Send> {
+    match (installation.token_url.clone(), installation.api_url.clone()) {
+        (Some(token_url), Some(api_url)) => ok((token_url, api_url)).boxed(),
+        _ => {
+            register_endpoints(installation.clone())
+                .map(|installation| (installation.token_url.unwrap(), installation.api_url.unwrap()))
+                .boxed()
+        }
+    }
+}
+
+fn request_token(installation: &Installation, token_url: &str) -> Result<oauth2::Token, oauth2::Error> {
+    oauth2::Oauth2::new(installation.oauth_id.to_owned(),
+                        installation.oauth_secret.to_owned(),
+                        token_url)
+            .map_err(|err| oauth2::Error::CommunicationError(format!("invalid token url: {:?}", err)))?
+            .exchange_token(oauth2::ClientCredentials { scope: Some("send_notification".to_string()) })
+}
+
+/// HipChat revokes the credentials of an add-on when it is uninstalled, so an installation is
+/// considered removed once the token endpoint rejects its credentials. Any other failure is an error,
+/// so that an installation is not removed because HipChat is unavailable.
+pub fn installation_revoked(installation: &Installation) -> Box<Future<Item = bool, Error = HipchatApiError> + Send> {
+    let installation = installation.clone();
+    endpoints(&installation)
+        .and_then(move |(token_url, _)| match request_token(&installation, &token_url) {
+                      Ok(_) => Ok(false),
+                      Err(ref err) if err.is_invalid_client() => Ok(true),
+                      Err(err) => Err(err)?,
+                  })
+        .boxed()
+}
+
+pub fn forget_token(oauth_id: &str) {
+    TOKEN_CACHE.lock().unwrap().remove(oauth_id);
+}
+
+pub fn send_notification(installation: &Installation,
+                         room_id: u32,
+                         notification: &RoomNotification)
+                         -> Box<Future<Item = (), Error = HipchatApiError> + Send> {
+    let body = serde_json::to_string(notification).unwrap();
+    let oauth_id = installation.oauth_id.clone();
+    api_access(installation)
+        .and_then(move |access| {
+            let url = Url::parse(&format!("{}/room/{}/notification", access.api_url, room_id)).unwrap();
+            let mut headers = Headers::new();
+            headers.set(access.token.bearer());
+            headers.set(ContentType::json());
+            future_request::post_async::<HipchatApiError>(url, headers, body)
+        })
+        .and_then(move |response| match StatusCode::from_u16(response.status_raw().0) {
+                      StatusCode::NoContent | StatusCode::Ok => Ok(()),
+                      StatusCode::Unauthorized => {
+                          forget_token(&oauth_id);
+                          Err(HipchatApiError { msg: "HipChat refused the token".to_string() })
+                      }
+                      status => Err(HipchatApiError { msg: format!("HipChat refused the notification: {}", status) }),
+                  })
+        .boxed()
+}
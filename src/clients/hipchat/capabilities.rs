@@ -0,0 +1,27 @@
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Oauth2Provider {
+    pub token_url: String,
+    pub authorization_url: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Capabilities {
+    pub oauth2_provider: Oauth2Provider,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Links {
+    pub api: String,
+    #[serde(rename = "self")]
+    pub self_url: String,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct ServerCapabilities {
+    pub key: String,
+    pub name: String,
+    pub capabilities: Capabilities,
+    pub links: Links,
+}
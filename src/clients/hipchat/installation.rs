@@ -1,4 +1,4 @@
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct Installation {
     pub oauth_id: String,
@@ -6,4 +6,8 @@ pub struct Installation {
     pub room_id: i32,
     pub group_id: i32,
     pub capabilities_url: String,
+    #[serde(default)]
+    pub token_url: Option<String>,
+    #[serde(default)]
+    pub api_url: Option<String>,
 }
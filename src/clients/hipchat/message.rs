@@ -12,3 +12,29 @@ pub enum Color {
     Purple,
     Red,
 }
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "lowercase")]
+pub enum MessageFormat {
+    Html,
+    Text,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct RoomNotification {
+    pub message: String,
+    pub color: Color,
+    pub message_format: MessageFormat,
+    pub notify: bool,
+}
+
+impl From<NotificationResponse> for RoomNotification {
+    fn from(notification: NotificationResponse) -> RoomNotification {
+        RoomNotification {
+            message: notification.message,
+            color: notification.color,
+            message_format: MessageFormat::Html,
+            notify: false,
+        }
+    }
+}
@@ -2,3 +2,5 @@ pub mod configuration;
 pub mod message;
 pub mod notification;
 pub mod installation;
+pub mod capabilities;
+pub mod api;
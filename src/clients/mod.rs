@@ -1,2 +1,3 @@
 pub mod witai;
 pub mod hipchat;
+pub mod slack;
@@ -0,0 +1,288 @@
+use std::collections::HashMap;
+
+use hyper;
+use hyper::Url;
+use hyper::header::{Headers, Authorization, Bearer, ContentType};
+use hyper::status::StatusCode;
+use futures::future::*;
+use serde_json;
+use crypto::hmac::Hmac;
+use crypto::mac::Mac;
+use crypto::sha2::Sha256;
+use crypto::util::fixed_time_eq;
+use url::form_urlencoded;
+
+use future_request;
+
+/// Requests older than this are rejected to prevent replays.
+pub const MAX_REQUEST_AGE: u64 = 60 * 5;
+
+#[derive(Debug, Clone)]
+pub struct SlashCommand {
+    pub team_id: String,
+    pub channel_id: String,
+    pub user_id: String,
+    pub command: String,
+    pub text: String,
+    pub response_url: String,
+}
+impl SlashCommand {
+    /// Slash commands are sent as `application/x-www-form-urlencoded` bodies.
+    pub fn from_form(body: &str) -> Option<SlashCommand> {
+        let fields = form_urlencoded::parse(body.as_bytes())
+            .into_owned()
+            .collect::<HashMap<String, String>>();
+        let field = |name: &str| fields.get(name).cloned();
+        match (field("team_id"), field("channel_id"), field("user_id"), field("command"), field("response_url")) {
+            (Some(team_id), Some(channel_id), Some(user_id), Some(command), Some(response_url)) => {
+                Some(SlashCommand {
+                         team_id,
+                         channel_id,
+                         user_id,
+                         command,
+                         text: field("text").unwrap_or_default(),
+                         response_url,
+                     })
+            }
+            _ => None,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Event {
+    #[serde(rename = "type")]
+    pub event_type: String,
+    pub channel: Option<String>,
+    pub user: Option<String>,
+    pub text: Option<String>,
+    pub bot_id: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum EventRequest {
+    UrlVerification { challenge: String },
+    EventCallback { team_id: String, event: Event },
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct Challenge {
+    pub challenge: String,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Text {
+    #[serde(rename = "type")]
+    pub text_type: String,
+    pub text: String,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Block {
+    #[serde(rename = "type")]
+    pub block_type: String,
+    pub text: Text,
+}
+impl Block {
+    pub fn section(text: &str) -> Block {
+        Block {
+            block_type: "section".to_string(),
+            text: Text {
+                text_type: "mrkdwn".to_string(),
+                text: text.to_string(),
+            },
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ResponseType {
+    InChannel,
+    Ephemeral,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct SlackMessage {
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub channel: Option<String>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub response_type: Option<ResponseType>,
+    pub text: String,
+    pub blocks: Vec<Block>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SignatureError {
+    InvalidTimestamp,
+    Stale,
+    BadSignature,
+}
+
+/// Checks a `X-Slack-Signature` header against the raw body, as described in Slack's request signing documentation.
+pub fn verify_signature(signing_secret: &str, timestamp: &str, body: &str, signature: &str, now: u64) -> Result<(), SignatureError> {
+    let sent_at = timestamp
+        .parse::<u64>()
+        .map_err(|_| SignatureError::InvalidTimestamp)?;
+    let age = if now > sent_at { now - sent_at } else { sent_at - now };
+    if age > MAX_REQUEST_AGE {
+        return Err(SignatureError::Stale);
+    }
+    if fixed_time_eq(sign(signing_secret, timestamp, body).as_bytes(), signature.as_bytes()) {
+        Ok(())
+    } else {
+        Err(SignatureError::BadSignature)
+    }
+}
+
+pub fn sign(signing_secret: &str, timestamp: &str, body: &str) -> String {
+    let mut hmac = Hmac::new(Sha256::new(), signing_secret.as_bytes());
+    hmac.input(format!("v0:{}:{}", timestamp, body).as_bytes());
+    let hex = hmac.result()
+        .code()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect::<String>();
+    format!("v0={}", hex)
+}
+
+#[derive(Debug, Clone)]
+pub struct SlackError {
+    msg: String,
+}
+impl From<hyper::Error> for SlackError {
+    fn from(err: hyper::Error) -> SlackError {
+        SlackError { msg: format!("couldn't contact Slack: {:?}", err) }
+    }
+}
+
+pub trait SlackClient: Send + Sync {
+    /// Answers a slash command through its `response_url`.
+    fn respond(&self, response_url: &str, message: &SlackMessage) -> Box<Future<Item = (), Error = SlackError> + Send>;
+    /// Posts a message in the channel set on `message`.
+    fn post_message(&self, message: &SlackMessage) -> Box<Future<Item = (), Error = SlackError> + Send>;
+}
+
+pub struct SlackApi {
+    bot_token: String,
+}
+impl SlackApi {
+    pub fn new(bot_token: String) -> SlackApi {
+        SlackApi { bot_token }
+    }
+
+    fn post(url: &str, headers: Headers, message: &SlackMessage) -> Box<Future<Item = (), Error = SlackError> + Send> {
+        let url = match Url::parse(url) {
+            Ok(url) => url,
+            Err(error) => return err(SlackError { msg: format!("invalid url: {:?}", error) }).boxed(),
+        };
+        future_request::post_async::<SlackError>(url, headers, serde_json::to_string(message).unwrap())
+            .and_then(|response| match StatusCode::from_u16(response.status_raw().0) {
+                          StatusCode::Ok => Ok(()),
+                          status => Err(SlackError { msg: format!("Slack refused the message: {}", status) }),
+                      })
+            .boxed()
+    }
+}
+impl SlackClient for SlackApi {
+    fn respond(&self, response_url: &str, message: &SlackMessage) -> Box<Future<Item = (), Error = SlackError> + Send> {
+        let mut headers = Headers::new();
+        headers.set(ContentType::json());
+        Self::post(response_url, headers, message)
+    }
+
+    fn post_message(&self, message: &SlackMessage) -> Box<Future<Item = (), Error = SlackError> + Send> {
+        let mut headers = Headers::new();
+        headers.set(ContentType::json());
+        headers.set(Authorization(Bearer { token: self.bot_token.clone() }));
+        Self::post("https://slack.com/api/chat.postMessage", headers, message)
+    }
+}
+
+#[cfg(test)]
+pub mod mock {
+    use std::sync::Mutex;
+
+    use futures::future::*;
+
+    use super::{SlackClient, SlackError, SlackMessage};
+
+    /// Records every message instead of sending it.
+    pub struct MockSlackClient {
+        pub sent: Mutex<Vec<(Option<String>, SlackMessage)>>,
+    }
+    impl MockSlackClient {
+        pub fn new() -> MockSlackClient {
+            MockSlackClient { sent: Mutex::new(vec![]) }
+        }
+    }
+    impl SlackClient for MockSlackClient {
+        fn respond(&self, response_url: &str, message: &SlackMessage) -> Box<Future<Item = (), Error = SlackError> + Send> {
+            self.sent
+                .lock()
+                .unwrap()
+                .push((Some(response_url.to_string()), message.clone()));
+            ok(()).boxed()
+        }
+
+        fn post_message(&self, message: &SlackMessage) -> Box<Future<Item = (), Error = SlackError> + Send> {
+            self.sent.lock().unwrap().push((None, message.clone()));
+            ok(()).boxed()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: &'static str = "8f742231b10e8888abcd99yyyzzz85a5";
+    const BODY: &'static str = "token=xyzz0WbapA4vBCDEFasx0q6G&team_id=T1DC2JH3J&command=%2Fsami&text=who+am+i";
+
+    #[test]
+    fn accepts_signed_request() {
+        let signature = sign(SECRET, "1531420618", BODY);
+        assert!(signature.starts_with("v0="));
+        assert_eq!(verify_signature(SECRET, "1531420618", BODY, &signature, 1531420618 + 10), Ok(()));
+    }
+
+    #[test]
+    fn rejects_tampered_or_stale_request() {
+        let signature = sign(SECRET, "1531420618", BODY);
+        assert_eq!(verify_signature(SECRET, "1531420618", "text=logout", &signature, 1531420618),
+                   Err(SignatureError::BadSignature));
+        assert_eq!(verify_signature(SECRET, "1531420618", BODY, &signature, 1531420618 + MAX_REQUEST_AGE + 1),
+                   Err(SignatureError::Stale));
+        assert_eq!(verify_signature(SECRET, "yesterday", BODY, &signature, 1531420618),
+                   Err(SignatureError::InvalidTimestamp));
+    }
+
+    #[test]
+    fn parses_slash_command() {
+        let command = SlashCommand::from_form("team_id=T1&channel_id=C2&user_id=U3&command=%2Fsami&text=who+am+i&\
+                                               response_url=https%3A%2F%2Fhooks.slack.com%2Fcommands%2F1")
+                .unwrap();
+        assert_eq!(command.channel_id, "C2");
+        assert_eq!(command.text, "who am i");
+        assert_eq!(command.response_url, "https://hooks.slack.com/commands/1");
+        assert!(SlashCommand::from_form("text=who+am+i").is_none());
+    }
+
+    #[test]
+    fn mock_client_records_messages() {
+        let client = mock::MockSlackClient::new();
+        let message = SlackMessage {
+            channel: Some("C123".to_string()),
+            response_type: None,
+            text: "hello".to_string(),
+            blocks: vec![Block::section("hello")],
+        };
+        client.post_message(&message).wait().unwrap();
+        client.respond("https://hooks.slack.com/commands/1", &message).wait().unwrap();
+        let sent = client.sent.lock().unwrap();
+        assert_eq!(sent.len(), 2);
+        assert_eq!(sent[1].0, Some("https://hooks.slack.com/commands/1".to_string()));
+        assert_eq!(sent[0].1, message);
+    }
+}
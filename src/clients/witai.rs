@@ -4,14 +4,129 @@ use futures::future::*;
 use std::collections::HashMap;
 use serde_json;
 use hyper;
+use time;
 use future_request;
 
 use CONFIGURATION;
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
-struct Value {
+struct Bound {
     value: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct Normalized {
+    value: f64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct Value {
+    /// Missing for intervals, which have `from` and `to` instead.
+    #[serde(default)]
+    value: serde_json::Value,
     confidence: f32,
+    grain: Option<String>,
+    from: Option<Bound>,
+    to: Option<Bound>,
+    /// Durations in seconds.
+    normalized: Option<Normalized>,
+}
+impl Value {
+    fn as_string(&self) -> String {
+        match self.value {
+            serde_json::Value::String(ref value) => value.clone(),
+            ref other => other.to_string(),
+        }
+    }
+
+    /// Reads the custom `aggregation` entity, whose values are "average", "minimum", "maximum", "sum" and "count".
+    fn as_aggregation(&self) -> Option<::sami::input::Aggregation> {
+        match self.as_string().as_ref() {
+            "average" => Some(::sami::input::Aggregation::Average),
+            "minimum" => Some(::sami::input::Aggregation::Minimum),
+            "maximum" => Some(::sami::input::Aggregation::Maximum),
+            "sum" => Some(::sami::input::Aggregation::Sum),
+            "count" => Some(::sami::input::Aggregation::Count),
+            _ => None,
+        }
+    }
+
+    /// Reads the custom `comparison` entity, whose values are "above" and "below".
+    fn as_comparison(&self) -> Option<::sami::input::Comparison> {
+        match self.as_string().as_ref() {
+            "above" => Some(::sami::input::Comparison::Above),
+            "below" => Some(::sami::input::Comparison::Below),
+            _ => None,
+        }
+    }
+
+    /// Reads the custom `interval` entity, whose values are the units of a histogram.
+    fn as_interval(&self) -> Option<::akc::message::Interval> {
+        match self.as_string().as_ref() {
+            "minute" => Some(::akc::message::Interval::Minute),
+            "hour" => Some(::akc::message::Interval::Hour),
+            "day" => Some(::akc::message::Interval::Day),
+            "month" => Some(::akc::message::Interval::Month),
+            "year" => Some(::akc::message::Interval::Year),
+            _ => None,
+        }
+    }
+
+    /// Reads a `datetime` entity, either an interval or an instant lasting its grain.
+    fn as_period(&self) -> Option<::sami::input::Period> {
+        match (&self.from, &self.to) {
+            (&Some(ref from), to) => {
+                let to = match *to {
+                    Some(ref to) => parse_datetime(&to.value),
+                    None => Some(time::get_time().sec as u64 * 1000),
+                };
+                match (parse_datetime(&from.value), to) {
+                    (Some(from), Some(to)) => Some(::sami::input::Period { from, to }),
+                    _ => None,
+                }
+            }
+            _ => {
+                let grain = match self.grain.as_ref().map(|grain| grain.as_ref()) {
+                    Some("second") => 1,
+                    Some("minute") => 60,
+                    Some("hour") => 60 * 60,
+                    Some("week") => 7 * 24 * 60 * 60,
+                    Some("month") => 30 * 24 * 60 * 60,
+                    Some("year") => 365 * 24 * 60 * 60,
+                    _ => 24 * 60 * 60,
+                };
+                parse_datetime(&self.as_string()).map(|from| {
+                                                          ::sami::input::Period {
+                                                              from,
+                                                              to: from + grain * 1000,
+                                                          }
+                                                      })
+            }
+        }
+    }
+}
+
+/// Parses wit.ai dates such as `2017-07-13T00:00:00.000-07:00` into milliseconds since epoch.
+fn parse_datetime(datetime: &str) -> Option<u64> {
+    if datetime.len() < 19 || !datetime.is_char_boundary(19) {
+        return None;
+    }
+    let local = match time::strptime(&datetime[..19], "%Y-%m-%dT%H:%M:%S") {
+        Ok(tm) => tm.to_timespec().sec,
+        Err(_) => return None,
+    };
+    let zone = &datetime[19..];
+    let offset = match zone.rfind(|c| c == '+' || c == '-') {
+        Some(index) if zone.len() == index + 6 => {
+            let sign = if zone[index..].starts_with('-') { -1 } else { 1 };
+            match (zone[index + 1..index + 3].parse::<i64>(), zone[index + 4..].parse::<i64>()) {
+                (Ok(hours), Ok(minutes)) => sign * (hours * 60 + minutes) * 60,
+                _ => return None,
+            }
+        }
+        _ => 0,
+    };
+    Some(((local - offset) * 1000) as u64)
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -30,7 +145,7 @@ impl From<Response> for ::sami::input::NlpResponse {
             Some(values) => {
                 let values = values
                     .iter()
-                    .map(|value| value.value.clone())
+                    .map(|value| value.as_string())
                     .collect::<Vec<String>>();
                 match values {
                     ref intent_self if intent_self.len() == 1 && intent_self[0] == "get_self" => {
@@ -54,7 +169,58 @@ impl From<Response> for ::sami::input::NlpResponse {
                                 .map(|values| {
                                          values
                                              .iter()
-                                             .map(|value| value.value.to_lowercase().clone())
+                                             .map(|value| value.as_string().to_lowercase())
+                                             .collect::<Vec<String>>()
+                                     }),
+                            field: response
+                                .entities
+                                .get("field")
+                                .and_then(|values| {
+                                              values
+                                                  .get(0)
+                                                  .map(|value| value.as_string().to_lowercase())
+                                          }),
+                            ..Default::default()
+                        }
+                    }
+                    ref intent_self if intent_self.len() == 1 && intent_self[0] == "set_field" => {
+                        ::sami::input::NlpResponse {
+                            intent: ::sami::Intent::SetField,
+                            device: response
+                                .entities
+                                .get("target")
+                                .map(|values| {
+                                         values
+                                             .iter()
+                                             .map(|value| value.as_string().to_lowercase())
+                                             .collect::<Vec<String>>()
+                                     }),
+                            field: response
+                                .entities
+                                .get("field")
+                                .and_then(|values| {
+                                              values
+                                                  .get(0)
+                                                  .map(|value| value.as_string().to_lowercase())
+                                          }),
+                            value: response
+                                .entities
+                                .get("value")
+                                .or_else(|| response.entities.get("number"))
+                                .and_then(|values| values.get(0).map(|value| value.as_string())),
+                            ..Default::default()
+                        }
+                    }
+                    ref intent_self if intent_self.len() == 1 && intent_self[0] == "get_history" => {
+                        ::sami::input::NlpResponse {
+                            intent: ::sami::Intent::GetHistory,
+                            device: response
+                                .entities
+                                .get("target")
+                                .map(|values| {
+                                         values
+                                             .iter()
+                                             .map(|value| value.as_string().to_lowercase())
                                              .collect::<Vec<String>>()
                                      }),
                             field: response
@@ -63,8 +229,195 @@ impl From<Response> for ::sami::input::NlpResponse {
                                 .and_then(|values| {
                                               values
                                                   .get(0)
-                                                  .map(|value| value.value.to_lowercase().clone())
+                                                  .map(|value| value.as_string().to_lowercase())
                                           }),
+                            period: response
+                                .entities
+                                .get("datetime")
+                                .and_then(|values| values.get(0).and_then(|value| value.as_period())),
+                            ..Default::default()
+                        }
+                    }
+                    ref intent_self if intent_self.len() == 1 && intent_self[0] == "get_aggregate" => {
+                        ::sami::input::NlpResponse {
+                            intent: ::sami::Intent::GetAggregate,
+                            device: response
+                                .entities
+                                .get("target")
+                                .map(|values| {
+                                         values
+                                             .iter()
+                                             .map(|value| value.as_string().to_lowercase())
+                                             .collect::<Vec<String>>()
+                                     }),
+                            field: response
+                                .entities
+                                .get("field")
+                                .and_then(|values| {
+                                              values
+                                                  .get(0)
+                                                  .map(|value| value.as_string().to_lowercase())
+                                          }),
+                            period: response
+                                .entities
+                                .get("datetime")
+                                .and_then(|values| values.get(0).and_then(|value| value.as_period())),
+                            aggregation: response
+                                .entities
+                                .get("aggregation")
+                                .and_then(|values| values.get(0).and_then(|value| value.as_aggregation())),
+                            interval: response
+                                .entities
+                                .get("interval")
+                                .and_then(|values| values.get(0).and_then(|value| value.as_interval())),
+                            ..Default::default()
+                        }
+                    }
+                    ref intent_self if intent_self.len() == 1 && intent_self[0] == "create_alert" => {
+                        ::sami::input::NlpResponse {
+                            intent: ::sami::Intent::CreateAlert,
+                            device: response
+                                .entities
+                                .get("target")
+                                .map(|values| {
+                                         values
+                                             .iter()
+                                             .map(|value| value.as_string().to_lowercase())
+                                             .collect::<Vec<String>>()
+                                     }),
+                            field: response
+                                .entities
+                                .get("field")
+                                .and_then(|values| {
+                                              values
+                                                  .get(0)
+                                                  .map(|value| value.as_string().to_lowercase())
+                                          }),
+                            value: response
+                                .entities
+                                .get("number")
+                                .or_else(|| response.entities.get("value"))
+                                .and_then(|values| values.get(0).map(|value| value.as_string())),
+                            comparison: response
+                                .entities
+                                .get("comparison")
+                                .and_then(|values| values.get(0).and_then(|value| value.as_comparison())),
+                            ..Default::default()
+                        }
+                    }
+                    ref intent_self if intent_self.len() == 1 && intent_self[0] == "list_alerts" => {
+                        ::sami::input::NlpResponse {
+                            intent: ::sami::Intent::ListAlerts,
+                            ..Default::default()
+                        }
+                    }
+                    ref intent_self if intent_self.len() == 1 && intent_self[0] == "delete_alert" => {
+                        ::sami::input::NlpResponse {
+                            intent: ::sami::Intent::DeleteAlert,
+                            value: response
+                                .entities
+                                .get("number")
+                                .and_then(|values| values.get(0).map(|value| value.as_string())),
+                            ..Default::default()
+                        }
+                    }
+                    ref intent_self if intent_self.len() == 1 && intent_self[0] == "watch" => {
+                        ::sami::input::NlpResponse {
+                            intent: ::sami::Intent::Watch,
+                            device: response
+                                .entities
+                                .get("target")
+                                .map(|values| {
+                                         values
+                                             .iter()
+                                             .map(|value| value.as_string().to_lowercase())
+                                             .collect::<Vec<String>>()
+                                     }),
+                            field: response
+                                .entities
+                                .get("field")
+                                .and_then(|values| {
+                                              values
+                                                  .get(0)
+                                                  .map(|value| value.as_string().to_lowercase())
+                                          }),
+                            duration: response
+                                .entities
+                                .get("duration")
+                                .and_then(|values| {
+                                              values
+                                                  .get(0)
+                                                  .and_then(|value| value.normalized.as_ref().map(|normalized| normalized.value as u64))
+                                          }),
+                            ..Default::default()
+                        }
+                    }
+                    ref intent_self if intent_self.len() == 1 && intent_self[0] == "stop_watching" => {
+                        ::sami::input::NlpResponse {
+                            intent: ::sami::Intent::StopWatching,
+                            ..Default::default()
+                        }
+                    }
+                    ref intent_self if intent_self.len() == 1 && intent_self[0] == "find_device_type" => {
+                        ::sami::input::NlpResponse {
+                            intent: ::sami::Intent::FindDeviceType,
+                            device_type: response
+                                .entities
+                                .get("device_type")
+                                .or_else(|| response.entities.get("target"))
+                                .map(|values| {
+                                         values
+                                             .iter()
+                                             .map(|value| value.as_string().to_lowercase())
+                                             .collect::<Vec<String>>()
+                                             .join(" ")
+                                     }),
+                            ..Default::default()
+                        }
+                    }
+                    ref intent_self if intent_self.len() == 1 && intent_self[0] == "describe_device" => {
+                        ::sami::input::NlpResponse {
+                            intent: ::sami::Intent::DescribeDevice,
+                            device: response
+                                .entities
+                                .get("target")
+                                .map(|values| {
+                                         values
+                                             .iter()
+                                             .map(|value| value.as_string().to_lowercase())
+                                             .collect::<Vec<String>>()
+                                     }),
+                            ..Default::default()
+                        }
+                    }
+                    ref intent_self if intent_self.len() == 1 && intent_self[0] == "list_devices" => {
+                        ::sami::input::NlpResponse {
+                            intent: ::sami::Intent::ListDevices,
+                            device: response
+                                .entities
+                                .get("target")
+                                .map(|values| {
+                                         values
+                                             .iter()
+                                             .map(|value| value.as_string().to_lowercase())
+                                             .collect::<Vec<String>>()
+                                     }),
+                            device_type: response
+                                .entities
+                                .get("device_type")
+                                .map(|values| {
+                                         values
+                                             .iter()
+                                             .map(|value| value.as_string().to_lowercase())
+                                             .collect::<Vec<String>>()
+                                             .join(" ")
+                                     }),
+                            ..Default::default()
+                        }
+                    }
+                    ref intent_self if intent_self.len() == 1 && intent_self[0] == "next_page" => {
+                        ::sami::input::NlpResponse {
+                            intent: ::sami::Intent::NextPage,
                             ..Default::default()
                         }
                     }
@@ -98,8 +451,22 @@ impl From<serde_json::Error> for WitAiError {
 
 pub struct WitAi {}
 
+impl ::sami::input::NlpBackend for WitAi {
+    fn understand(&self, query: &str) -> Box<Future<Item = ::sami::input::NlpResponse, Error = ::sami::input::NlpError> + Send> {
+        WitAi::get(query)
+            .map(|response| {
+                     ::sami::input::NlpResponse {
+                         query: response._text.clone(),
+                         ..::sami::input::NlpResponse::from(response)
+                     }
+                 })
+            .map_err(|err| ::sami::input::NlpError { msg: err.msg })
+            .boxed()
+    }
+}
+
 impl WitAi {
-    pub fn get(query: &str) -> Box<Future<Item = Response, Error = WitAiError>> {
+    pub fn get(query: &str) -> Box<Future<Item = Response, Error = WitAiError> + Send> {
         let mut url = Url::parse("https://api.wit.ai/message").unwrap();
         url.query_pairs_mut()
             .append_pair("v", &CONFIGURATION.witai_version)
@@ -115,3 +482,15 @@ impl WitAi {
             .boxed()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::parse_datetime;
+
+    #[test]
+    fn parses_dates_with_offset() {
+        assert_eq!(parse_datetime("2017-07-13T17:00:00.000-07:00"), Some(1_499_990_400_000));
+        assert_eq!(parse_datetime("2017-07-14T00:00:00.000+00:00"), Some(1_499_990_400_000));
+        assert_eq!(parse_datetime("yesterday"), None);
+    }
+}
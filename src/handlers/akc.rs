@@ -9,6 +9,7 @@ use iron::prelude::*;
 use DATABASE;
 use CONFIGURATION;
 use oauth2;
+use akc;
 
 create_handler!(ExchangeToken, |_: &ExchangeToken, req: &mut Request| {
     let params = get_query_params!(req, "code", "state");
@@ -16,7 +17,7 @@ create_handler!(ExchangeToken, |_: &ExchangeToken, req: &mut Request| {
         (Some(code), Some(state), _) => {
             match oauth2::Oauth2::new(CONFIGURATION.akc_appid.to_owned(),
                                       CONFIGURATION.akc_appsecret.to_owned(),
-                                      "https://accounts.artik.cloud/token")
+                                      akc::Akc::token_url())
                           .unwrap()
                           .exchange_token(oauth2::AuthorizationCode { code: code[0].to_owned() }) {
                 Ok(token) => {
@@ -24,7 +25,7 @@ create_handler!(ExchangeToken, |_: &ExchangeToken, req: &mut Request| {
                         .lock()
                         .unwrap()
                         .add_token(state[0].to_string(), token);
-                    let mut response = Response::with((status::Ok, "You can now return to hipchat"));
+                    let mut response = Response::with((status::Ok, "You can now return to your chat room"));
                     response.headers.set(ContentType::html());
                     Ok(response)
                 }
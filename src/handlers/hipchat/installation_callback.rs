@@ -1,35 +1,129 @@
+use std::sync::mpsc;
+use std::time::Duration;
+
 use iron::{Handler, status, IronResult, Response, Request};
 use bodyparser;
 use iron::prelude::*;
 use router::Router;
+use futures::Future;
 
+use clients::hipchat::api;
 use clients::hipchat::installation::Installation;
 
+use handlers::lib::my_error::MyError;
 use handlers::hipchat::HC_DATABASE;
 
+use sami::alerts::ALERTS;
+
+use timer;
+
+use DATABASE;
+use CPU_POOL;
+
+/// How long, in seconds, HipChat is kept waiting while the capabilities of a new installation are checked.
+const CAPABILITIES_TIMEOUT: u64 = 10;
+/// Delays, in seconds, before each check that an installation is revoked after an uninstall request, as HipChat
+/// may only revoke its credentials after calling the uninstall endpoint.
+const REVOCATION_CHECKS: [u64; 5] = [0, 10, 60, 300, 900];
+
 create_handler!(PostInstallation,
                 |_: &PostInstallation, req: &mut Request| {
     let installation = req.get::<bodyparser::Struct<Installation>>();
     match installation {
         Ok(Some(installation)) => {
-            let mut locked = HC_DATABASE.lock().unwrap();
-            locked.add_installation(installation);
+            if !api::is_hipchat_url(&installation.capabilities_url) {
+                warn!("rejecting installation from {:?}", installation.capabilities_url);
+                return MyError::http_error(status::BadRequest, "capabilities are not on a HipChat server");
+            }
+            // the capabilities are fetched on the pool so that a slow HipChat server only holds the worker for a bounded time,
+            // a registration finishing after the timeout is dropped
+            let (registered, registration) = mpsc::channel();
+            CPU_POOL
+                .spawn(api::register_endpoints(installation).then(move |result| {
+                                                                      let _ = registered.send(result);
+                                                                      Ok::<(), ()>(())
+                                                                  }))
+                .forget();
+            match registration.recv_timeout(Duration::from_secs(CAPABILITIES_TIMEOUT)) {
+                Ok(Ok(installation)) => {
+                    let mut locked = HC_DATABASE.lock().unwrap();
+                    locked.add_installation(installation);
+                    Ok(Response::with((status::Ok, "{}".to_string())))
+                }
+                Ok(Err(err)) => {
+                    warn!("rejecting installation: {:?}", err);
+                    MyError::http_error(status::BadRequest, "couldn't retrieve HipChat capabilities")
+                }
+                Err(_) => {
+                    warn!("rejecting installation: capabilities not retrieved after {} seconds",
+                          CAPABILITIES_TIMEOUT);
+                    MyError::http_error(status::BadRequest, "couldn't retrieve HipChat capabilities in time")
+                }
+            }
+        }
+        Ok(None) => MyError::http_error(status::BadRequest, "missing body"),
+        Err(err) => {
+            MyError::http_error(status::BadRequest,
+                                &format!("invalid JSON: {:?}", err).to_string())
         }
-        Ok(None) => info!("No body"),
-        Err(err) => info!("Error: {:?}", err),
     }
-    Ok(Response::with((status::Ok, "{}".to_string())))
 });
 
 create_handler!(DeleteInstallation,
                 |_: &DeleteInstallation, req: &mut Request| {
-    let installation = get_path_param!(req, "installation").to_string();
-    info!("removing {:?}", installation);
-    let body = req.get::<bodyparser::Raw>();
-    match body {
-        Ok(Some(body)) => info!("Read body: {}", body),
-        Ok(None) => info!("No body"),
-        Err(err) => info!("Error: {:?}", err),
-    }
-    Ok(Response::with((status::Ok, "".to_string())))
+    let oauth_id = get_path_param!(req, "installation").to_string();
+    let installation = {
+        let locked = HC_DATABASE.lock().unwrap();
+        locked.get_installation(oauth_id.clone()).cloned()
+    };
+    let installation = match installation {
+        Some(installation) => installation,
+        None => return MyError::http_error(status::NotFound, "unknown installation"),
+    };
+    info!("uninstall requested for {:?}", oauth_id);
+    remove_once_revoked(installation, &REVOCATION_CHECKS);
+    Ok(Response::with(status::NoContent))
 });
+
+/// Removes the installation and everything stored for its rooms once HipChat has revoked it, checking again after
+/// each of `checks` until it is.
+fn remove_once_revoked(installation: Installation, checks: &'static [u64]) {
+    let (delay, next_checks) = match checks.split_first() {
+        Some((delay, next_checks)) => (*delay, next_checks),
+        None => {
+            warn!("ignoring uninstall request for {:?}: installation is still active",
+                  installation.oauth_id);
+            return;
+        }
+    };
+    let check = timer::sleep(Duration::from_secs(delay)).and_then(move |_| {
+        api::installation_revoked(&installation).then(move |revoked| {
+            match revoked {
+                Ok(true) => remove_installation(&installation.oauth_id),
+                Ok(false) => remove_once_revoked(installation, next_checks),
+                Err(err) => {
+                    error!("couldn't check whether the installation was revoked: {:?}", err);
+                    remove_once_revoked(installation, next_checks)
+                }
+            }
+            Ok(())
+        })
+    });
+    CPU_POOL.spawn(check).forget();
+}
+
+fn remove_installation(oauth_id: &str) {
+    HC_DATABASE
+        .lock()
+        .unwrap()
+        .remove_installation(oauth_id);
+    DATABASE
+        .lock()
+        .unwrap()
+        .remove_tokens_with_prefix(&format!("hipchatroom-{}-", oauth_id));
+    ALERTS
+        .lock()
+        .unwrap()
+        .remove_contexts_with_prefix(&format!("hipchatroom-{}-", oauth_id));
+    api::forget_token(oauth_id);
+}
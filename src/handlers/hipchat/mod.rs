@@ -1,40 +1,35 @@
-//use std::collections::Vec;
 use std::sync::{Arc, Mutex};
 
+use clients::hipchat::installation::Installation;
+use storage::{Storage, MemoryStorage};
+
 pub mod descriptor;
 pub mod notification;
 pub mod installation_callback;
+pub mod verification;
 
-#[derive(Clone, Debug)]
 pub struct Database {
-    installations: Vec<::clients::hipchat::installation::Installation>,
+    installations: Box<Storage<Installation>>,
 }
 impl Database {
-    pub fn new() -> Database {
-        Database { installations: vec![] }
+    pub fn new(installations: Box<Storage<Installation>>) -> Database {
+        Database { installations }
     }
-    pub fn add_installation(&mut self, installation: ::clients::hipchat::installation::Installation) {
+    pub fn add_installation(&mut self, installation: Installation) {
         info!("adding installation {:?}", installation);
-        self.installations.push(installation);
+        self.installations
+            .insert(installation.oauth_id.clone(), installation);
     }
-    pub fn get_installation(&self, key: String) -> Option<&::clients::hipchat::installation::Installation> {
+    pub fn get_installation(&self, key: String) -> Option<&Installation> {
         info!("getting installation for key {:?}", key);
-        self.installations
-            .iter()
-            .find(|installation| installation.oauth_id == key)
+        self.installations.get(&key)
+    }
+    pub fn remove_installation(&mut self, key: &str) -> Option<Installation> {
+        info!("removing installation for key {:?}", key);
+        self.installations.remove(key)
     }
-    /*    pub fn remove_token(&mut self, access_token: String) {
-        let keys_to_remove: Vec<String> = self.installations
-            .clone()
-            .iter()
-            .filter(|&(_, v)| v.access_token() == access_token)
-            .map(|(k, _)| k.clone())
-            .collect();
-        for key in keys_to_remove {
-            self.installations.remove(&key);
-        }
-    }*/
 }
 lazy_static! {
-    static ref HC_DATABASE: Arc<Mutex<Database>> = Arc::new(Mutex::new(Database::new()));
+    /// Kept in memory until `main` opens the storage.
+    pub static ref HC_DATABASE: Arc<Mutex<Database>> = Arc::new(Mutex::new(Database::new(Box::new(MemoryStorage::new()))));
 }
@@ -1,73 +1,33 @@
+use std::sync::Arc;
+
 use iron::{Handler, status, IronResult, Response, Request};
 use bodyparser;
 use iron::prelude::*;
 use serde_json;
 use futures::Future;
-use hyper::header::{Authorization, Scheme};
-use hyper::error::Error;
-use std::str::FromStr;
-use jwt::{Header, Registered, Token};
-use crypto::sha2::Sha256;
+use time;
 
-use clients::witai::WitAi;
 use sami;
+use sami::render::{Renderer, Html};
 
 use handlers::lib::my_error::MyError;
 
 use DATABASE;
 use handlers::hipchat::HC_DATABASE;
+use handlers::hipchat::verification;
 
 use CONFIGURATION;
+use CPU_POOL;
+use NLP_BACKEND;
 
+use clients::hipchat::api;
 use clients::hipchat::message::*;
 use clients::hipchat::notification::*;
 
 fn notification_from_message(message: sami::output::MessageToUser) -> NotificationResponse {
     info!("{:?}", message);
     NotificationResponse {
-        message: match message.intent {
-            ::sami::Intent::GetSelf => format!("You are connected as {}.", message.data[0]),
-            ::sami::Intent::Logout => {
-                DATABASE
-                    .lock()
-                    .unwrap()
-                    .remove_token(message.data[0].clone());
-                "You are now logged out.".to_string()
-            }
-            ::sami::Intent::ForcedLogout => {
-                DATABASE
-                    .lock()
-                    .unwrap()
-                    .remove_token(message.data[0].clone());
-                "Error communicating with ARTIK Cloud. You have been logged out.".to_string()
-            }
-            ::sami::Intent::GetField => {
-                match message.data.len() {
-                    1 => format!("No device found for '{}'.", message.data[0]),
-                    2 => {
-                        format!("No field '{}' found for device '{}'.",
-                                message.data[1],
-                                message.data[0])
-                    }
-                    3 => {
-                        format!("{}'s {} is {}.",
-                                message.data[0],
-                                message.data[1],
-                                message.data[2])
-                    }
-                    _ => "uuuh ?".to_string(),
-                }
-            }
-            ::sami::Intent::Unknown => {
-                format!("Unknown intent: {:?}",
-                        if !message.data.is_empty() {
-                            message.data[0].clone()
-                        } else {
-                            "'no intent found'".to_string()
-                        })
-            }
-            intent => format!("{:?} not yet done", intent),
-        },
+        message: Html.render(&message),
         color: match message.status {
             sami::output::Status::Info => Color::Purple,
             sami::output::Status::Confirmation => Color::Green,
@@ -77,75 +37,100 @@ fn notification_from_message(message: sami::output::MessageToUser) -> Notificati
     }
 }
 
-#[derive(Clone, Debug)]
-struct JWT {
-    iss: String,
-    sub: String,
-    exp: u64,
-    iat: u64,
-    jti: String,
-}
-use std::fmt;
-impl Scheme for JWT {
-    fn scheme() -> Option<&'static str> {
-        Some("JWT")
-    }
-
-    fn fmt_scheme(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        f.write_str(&self.iss)
+/// Posts a message to a room without being asked, as when an alert fires.
+pub fn notify_room(oauth_id: &str, room_id: u32, message: sami::output::MessageToUser) -> Box<Future<Item = (), Error = ()> + Send> {
+    let installation = {
+        let locked = HC_DATABASE.lock().unwrap();
+        locked.get_installation(oauth_id.to_string()).cloned()
+    };
+    match installation {
+        Some(installation) => {
+            let mut notification = RoomNotification::from(notification_from_message(message));
+            notification.notify = true;
+            api::send_notification(&installation, room_id, &notification)
+                .map_err(|err| warn!("error sending notification: {:?}", err))
+                .boxed()
+        }
+        None => {
+            warn!("no installation {:?} to notify room {}", oauth_id, room_id);
+            ::futures::future::err(()).boxed()
+        }
     }
 }
-impl FromStr for JWT {
-    type Err = Error;
-    fn from_str(s: &str) -> Result<JWT, Error> {
-        let token = Token::<Header, Registered>::parse(s).unwrap();
-        let secret = {
-            let locked = HC_DATABASE.lock().unwrap();
-            let installation = locked.get_installation(token.claims.iss.clone().unwrap());
-            if !installation.is_some() {
-                return Err(Error::Header);
-            }
-            installation.unwrap().clone().oauth_secret
-        };
-        if token.verify(secret.as_bytes(), Sha256::new()) {
-            Ok(JWT {
-                   iss: token.claims.iss.unwrap(),
-                   sub: token.claims.sub.unwrap(),
-                   exp: token.claims.exp.unwrap(),
-                   iat: token.claims.iat.unwrap(),
-                   jti: token.claims.jti.unwrap(),
-               })
-        } else {
-            Err(Error::Header)
-        }
+
+fn verify_jwt(req: &Request) -> Result<verification::VerifiedJwt, verification::JwtError> {
+    let raw = match req.headers.get_raw("Authorization") {
+        Some(values) if !values.is_empty() => String::from_utf8_lossy(&values[0]).into_owned(),
+        _ => return Err(verification::JwtError::Malformed),
+    };
+    if !raw.starts_with("JWT ") {
+        return Err(verification::JwtError::Malformed);
     }
+    let path = format!("/{}", req.url.path().join("/"));
+    let method = req.method.to_string();
+    let request = verification::CanonicalRequest {
+        method: &method,
+        path: &path,
+        query: req.url.query(),
+    };
+    verification::verify(&raw[4..],
+                         |iss| {
+                             let locked = HC_DATABASE.lock().unwrap();
+                             locked
+                                 .get_installation(iss.to_string())
+                                 .map(|installation| installation.oauth_secret.clone())
+                         },
+                         &request,
+                         time::get_time().sec as u64)
 }
 
 create_handler!(ReceiveNotification,
                 |_: &ReceiveNotification, req: &mut Request| {
-    {
-        let auth = req.headers.get::<Authorization<JWT>>();
-        if !auth.is_some() {
-            return MyError::http_error(status::Unauthorized, "invalid JWT");
-        }
+    if let Err(err) = verify_jwt(req) {
+        warn!("rejecting notification: {:?}", err);
+        return MyError::http_error(status::Unauthorized, "invalid JWT");
     }
     let struct_body = req.get::<bodyparser::Struct<Notification>>();
     match struct_body {
         Ok(Some(struct_body)) => {
-            let context_identifier = format!("hipchatroom-{}-{}",
-                                             struct_body.oauth_client_id,
-                                             struct_body.item.room.unwrap().id);
+            // only messages sent in a room can be answered
+            let (room_id, text) = match (struct_body.item.room.as_ref(), struct_body.item.message.as_ref()) {
+                (Some(room), Some(message)) => (room.id, message.message.clone()),
+                _ => return Ok(Response::with(status::NoContent)),
+            };
+            let context_identifier = format!("hipchatroom-{}-{}", struct_body.oauth_client_id, room_id);
             //wrapped to release lock but keep info on presence
             let akc_access_token = {
                 let locked = DATABASE.lock().unwrap();
                 locked.get_token(context_identifier.clone()).cloned()
             };
             if let Some(akc_access_token) = akc_access_token {
-                let trigger = &struct_body.item.message.unwrap().message[(CONFIGURATION.hipchat_command.len() + 1)..];
-                let wit_ai_response_future = WitAi::get(trigger);
-                let wit_ai_response = sami::input::NlpResponse::from(wit_ai_response_future.wait().unwrap());
-                let message = sami::output::MessageToUser::from(akc_access_token.clone(), wit_ai_response);
-                Ok(Response::with((status::Ok, serde_json::to_string(&notification_from_message(message)).unwrap())))
+                let installation = {
+                    let locked = HC_DATABASE.lock().unwrap();
+                    locked
+                        .get_installation(struct_body.oauth_client_id.clone())
+                        .cloned()
+                };
+                let installation = match installation {
+                    Some(installation) => installation,
+                    None => return MyError::http_error(status::Unauthorized, "unknown installation"),
+                };
+                let trigger = text.trim_left_matches(&CONFIGURATION.hipchat_command[..]).trim();
+                let oauth_id = struct_body.oauth_client_id.clone();
+                let deliver: sami::output::Deliver = Arc::new(move |message: sami::output::MessageToUser| {
+                                                                  notify_room(&oauth_id, room_id, message)
+                                                              });
+                let answer = NLP_BACKEND
+                    .understand(trigger)
+                    .map_err(|err| warn!("error understanding message: {:?}", err))
+                    .and_then(move |nlp_response| {
+                        let message = sami::output::MessageToUser::from(&context_identifier, akc_access_token, nlp_response, deliver);
+                        let notification = RoomNotification::from(notification_from_message(message));
+                        api::send_notification(&installation, room_id, &notification)
+                            .map_err(|err| warn!("error sending notification: {:?}", err))
+                    });
+                CPU_POOL.spawn(answer).forget();
+                Ok(Response::with(status::NoContent))
             } else {
                 let signin_message = format!("This room is not authenticated.
                 Please <a href=\"https://accounts.artik.cloud/authorize?client_id={}&amp;state={}&amp;response_type=code\">sign in</a>.",
@@ -0,0 +1,248 @@
+use crypto::digest::Digest;
+use crypto::sha2::Sha256;
+use jwt::{Header, Token};
+use url::form_urlencoded;
+
+/// Tolerance applied to `exp` and `iat` to absorb clock differences with the HipChat server.
+pub const CLOCK_SKEW: u64 = 60;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum JwtError {
+    Malformed,
+    MissingClaim(&'static str),
+    UnknownIssuer(String),
+    BadSignature,
+    Expired,
+    IssuedInFuture,
+    InvalidQueryHash,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+struct Claims {
+    #[serde(skip_serializing_if="Option::is_none")]
+    iss: Option<String>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    sub: Option<String>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    exp: Option<u64>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    iat: Option<u64>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    jti: Option<String>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    qsh: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct VerifiedJwt {
+    pub iss: String,
+    pub sub: Option<String>,
+    pub exp: u64,
+    pub iat: u64,
+    pub jti: Option<String>,
+}
+
+/// The parts of a request covered by the `qsh` claim.
+pub struct CanonicalRequest<'a> {
+    pub method: &'a str,
+    pub path: &'a str,
+    pub query: Option<&'a str>,
+}
+
+fn percent_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|byte| match byte {
+                 b'A'...b'Z' | b'a'...b'z' | b'0'...b'9' | b'-' | b'_' | b'.' | b'~' => (byte as char).to_string(),
+                 byte => format!("%{:02X}", byte),
+             })
+        .collect()
+}
+
+impl<'a> CanonicalRequest<'a> {
+    fn canonical_query(&self) -> String {
+        let mut params: Vec<(String, Vec<String>)> = vec![];
+        if let Some(query) = self.query {
+            for (key, value) in form_urlencoded::parse(query.as_bytes()) {
+                if key == "jwt" {
+                    continue;
+                }
+                let key = percent_encode(&key);
+                let value = percent_encode(&value);
+                match params.iter().position(|&(ref existing, _)| *existing == key) {
+                    Some(index) => params[index].1.push(value),
+                    None => params.push((key, vec![value])),
+                }
+            }
+        }
+        params.sort_by(|a, b| a.0.cmp(&b.0));
+        params
+            .into_iter()
+            .map(|(key, mut values)| {
+                     values.sort();
+                     format!("{}={}", key, values.join(","))
+                 })
+            .collect::<Vec<String>>()
+            .join("&")
+    }
+
+    pub fn query_string_hash(&self) -> String {
+        let path = self.path.trim_right_matches('/');
+        let canonical = format!("{}&{}&{}",
+                                self.method.to_uppercase(),
+                                if path.is_empty() { "/" } else { path },
+                                self.canonical_query());
+        let mut hasher = Sha256::new();
+        hasher.input_str(&canonical);
+        hasher.result_str()
+    }
+}
+
+/// Verifies a HipChat JWT, looking up the shared secret of its issuer with `secret_for`.
+pub fn verify<F>(raw: &str, secret_for: F, request: &CanonicalRequest, now: u64) -> Result<VerifiedJwt, JwtError>
+    where F: Fn(&str) -> Option<String>
+{
+    let token = Token::<Header, Claims>::parse(raw).map_err(|_| JwtError::Malformed)?;
+    let iss = token.claims.iss.clone().ok_or(JwtError::MissingClaim("iss"))?;
+    let secret = secret_for(&iss).ok_or_else(|| JwtError::UnknownIssuer(iss.clone()))?;
+    if !token.verify(secret.as_bytes(), Sha256::new()) {
+        return Err(JwtError::BadSignature);
+    }
+    let exp = token.claims.exp.ok_or(JwtError::MissingClaim("exp"))?;
+    let iat = token.claims.iat.ok_or(JwtError::MissingClaim("iat"))?;
+    if exp + CLOCK_SKEW < now {
+        return Err(JwtError::Expired);
+    }
+    if iat > now + CLOCK_SKEW {
+        return Err(JwtError::IssuedInFuture);
+    }
+    let qsh = token.claims.qsh.as_ref().ok_or(JwtError::MissingClaim("qsh"))?;
+    if *qsh != request.query_string_hash() {
+        return Err(JwtError::InvalidQueryHash);
+    }
+    Ok(VerifiedJwt {
+           iss,
+           sub: token.claims.sub.clone(),
+           exp,
+           iat,
+           jti: token.claims.jti.clone(),
+       })
+}
+
+#[cfg(test)]
+mod tests {
+    use crypto::sha2::Sha256;
+    use jwt::{Header, Token};
+
+    use super::*;
+
+    const NOW: u64 = 1_500_000_000;
+
+    fn secret_for(iss: &str) -> Option<String> {
+        if iss == "installation" {
+            Some("secret".to_string())
+        } else {
+            None
+        }
+    }
+
+    fn claims() -> Claims {
+        Claims {
+            iss: Some("installation".to_string()),
+            sub: Some("42".to_string()),
+            exp: Some(NOW + 300),
+            iat: Some(NOW),
+            jti: Some("jti".to_string()),
+            qsh: Some(request().query_string_hash()),
+        }
+    }
+
+    fn sign(claims: Claims, secret: &str) -> String {
+        Token::new(Header::default(), claims)
+            .signed(secret.as_bytes(), Sha256::new())
+            .unwrap()
+    }
+
+    fn request<'a>() -> CanonicalRequest<'a> {
+        CanonicalRequest {
+            method: "post",
+            path: "/hipchat/notification",
+            query: Some("b=2&a=1&jwt=ignored"),
+        }
+    }
+
+    #[test]
+    fn accepts_valid_token() {
+        let verified = verify(&sign(claims(), "secret"), secret_for, &request(), NOW).unwrap();
+        assert_eq!(verified.iss, "installation");
+        assert_eq!(verified.sub, Some("42".to_string()));
+    }
+
+    #[test]
+    fn rejects_malformed_token() {
+        assert_eq!(verify("not a token", secret_for, &request(), NOW).unwrap_err(),
+                   JwtError::Malformed);
+    }
+
+    #[test]
+    fn rejects_bad_signature() {
+        assert_eq!(verify(&sign(claims(), "other"), secret_for, &request(), NOW).unwrap_err(),
+                   JwtError::BadSignature);
+    }
+
+    #[test]
+    fn rejects_unknown_issuer() {
+        let claims = Claims { iss: Some("stranger".to_string()), ..claims() };
+        assert_eq!(verify(&sign(claims, "secret"), secret_for, &request(), NOW).unwrap_err(),
+                   JwtError::UnknownIssuer("stranger".to_string()));
+    }
+
+    #[test]
+    fn rejects_missing_claim() {
+        let claims = Claims { exp: None, ..claims() };
+        assert_eq!(verify(&sign(claims, "secret"), secret_for, &request(), NOW).unwrap_err(),
+                   JwtError::MissingClaim("exp"));
+    }
+
+    #[test]
+    fn tolerates_clock_skew_on_expiration() {
+        let token = sign(claims(), "secret");
+        assert!(verify(&token, secret_for, &request(), NOW + 300 + CLOCK_SKEW).is_ok());
+        assert_eq!(verify(&token, secret_for, &request(), NOW + 301 + CLOCK_SKEW).unwrap_err(),
+                   JwtError::Expired);
+    }
+
+    #[test]
+    fn rejects_missing_query_string_hash() {
+        let claims = Claims { qsh: None, ..claims() };
+        assert_eq!(verify(&sign(claims, "secret"), secret_for, &request(), NOW).unwrap_err(),
+                   JwtError::MissingClaim("qsh"));
+    }
+
+    #[test]
+    fn rejects_token_issued_in_future() {
+        let claims = Claims { iat: Some(NOW + CLOCK_SKEW + 1), ..claims() };
+        assert_eq!(verify(&sign(claims, "secret"), secret_for, &request(), NOW).unwrap_err(),
+                   JwtError::IssuedInFuture);
+        let claims = Claims { iat: Some(NOW + CLOCK_SKEW), ..claims() };
+        assert!(verify(&sign(claims, "secret"), secret_for, &request(), NOW).is_ok());
+    }
+
+    #[test]
+    fn checks_query_string_hash() {
+        let claims_with_qsh = Claims { qsh: Some("0000".to_string()), ..claims() };
+        assert_eq!(verify(&sign(claims_with_qsh, "secret"), secret_for, &request(), NOW).unwrap_err(),
+                   JwtError::InvalidQueryHash);
+    }
+
+    #[test]
+    fn canonical_query_is_sorted_and_ignores_jwt() {
+        let first = request();
+        let second = CanonicalRequest {
+            method: "POST",
+            path: "/hipchat/notification/",
+            query: Some("a=1&jwt=other&b=2"),
+        };
+        assert_eq!(first.query_string_hash(), second.query_string_hash());
+    }
+}
@@ -3,6 +3,7 @@ mod lib;
 
 pub mod about;
 pub mod hipchat;
+pub mod slack;
 pub mod akc;
 
 pub mod test;
@@ -0,0 +1,415 @@
+use std::sync::Arc;
+
+use iron::{Handler, status, IronResult, Response, Request};
+use bodyparser;
+use iron::prelude::*;
+use serde_json;
+use futures::Future;
+use time;
+
+use sami;
+use sami::input::NlpBackend;
+use sami::render::{Card, Mrkdwn};
+
+use handlers::lib::my_error::MyError;
+
+use DATABASE;
+use CONFIGURATION;
+use CPU_POOL;
+
+use clients::slack::*;
+
+fn slack_message_from(message: sami::output::MessageToUser) -> SlackMessage {
+    info!("{:?}", message);
+    let card = Card::render(&Mrkdwn, &message);
+    let icon = match message.status {
+        sami::output::Status::Info => ":information_source:",
+        sami::output::Status::Confirmation => ":white_check_mark:",
+        sami::output::Status::Error => ":x:",
+        sami::output::Status::ActionRequired => ":warning:",
+    };
+    SlackMessage {
+        channel: None,
+        response_type: Some(ResponseType::InChannel),
+        text: card.summary,
+        blocks: vec![Block::section(&format!("{} {}", icon, card.body))],
+    }
+}
+
+fn signin_message(context_identifier: &str) -> SlackMessage {
+    let text = format!(":warning: This channel is not authenticated. \
+                        Please <https://accounts.artik.cloud/authorize?client_id={}&state={}&response_type=code|sign in>.",
+                       CONFIGURATION.akc_appid,
+                       context_identifier);
+    SlackMessage {
+        channel: None,
+        response_type: Some(ResponseType::Ephemeral),
+        text: "This channel is not authenticated.".to_string(),
+        blocks: vec![Block::section(&text)],
+    }
+}
+
+fn context_identifier(team_id: &str, channel_id: &str) -> String {
+    format!("slackchannel-{}-{}", team_id, channel_id)
+}
+
+fn answer(context_identifier: String,
+          channel: String,
+          akc_access_token: ::oauth2::Token,
+          text: &str,
+          client: Arc<SlackClient>,
+          nlp_backend: &NlpBackend)
+          -> Box<Future<Item = SlackMessage, Error = ()> + Send> {
+    let deliver: sami::output::Deliver =
+        Arc::new(move |message: sami::output::MessageToUser| notify_channel(&*client, channel.clone(), message));
+    nlp_backend
+        .understand(text)
+        .map_err(|err| warn!("error understanding message: {:?}", err))
+        .map(move |nlp_response| {
+                 slack_message_from(sami::output::MessageToUser::from(&context_identifier, akc_access_token, nlp_response, deliver))
+             })
+        .boxed()
+}
+
+/// Posts a message to a channel without being asked, as when an alert fires.
+pub fn notify_channel(client: &SlackClient,
+                      channel: String,
+                      message: sami::output::MessageToUser)
+                      -> Box<Future<Item = (), Error = ()> + Send> {
+    let mut message = slack_message_from(message);
+    message.channel = Some(channel);
+    message.response_type = None;
+    client
+        .post_message(&message)
+        .map_err(|err| warn!("error posting Slack message: {:?}", err))
+        .boxed()
+}
+
+fn raw_header(req: &Request, name: &str) -> Option<String> {
+    req.headers
+        .get_raw(name)
+        .and_then(|values| values.get(0).cloned())
+        .map(|value| String::from_utf8_lossy(&value).into_owned())
+}
+
+/// The parts of a request needed to check its signature.
+struct SignedBody {
+    timestamp: Option<String>,
+    signature: Option<String>,
+    body: String,
+}
+impl SignedBody {
+    fn from(req: &mut Request) -> Result<SignedBody, IronResult<Response>> {
+        let timestamp = raw_header(req, "X-Slack-Request-Timestamp");
+        let signature = raw_header(req, "X-Slack-Signature");
+        match req.get::<bodyparser::Raw>() {
+            Ok(Some(body)) => {
+                Ok(SignedBody {
+                       timestamp,
+                       signature,
+                       body,
+                   })
+            }
+            Ok(None) => Err(MyError::http_error(status::BadRequest, "missing body")),
+            Err(err) => Err(MyError::http_error(status::BadRequest, &format!("invalid body: {:?}", err))),
+        }
+    }
+
+    fn verified(self, signing_secret: &str) -> Result<String, IronResult<Response>> {
+        match (self.timestamp, self.signature) {
+            (Some(timestamp), Some(signature)) => {
+                match verify_signature(signing_secret, &timestamp, &self.body, &signature, time::get_time().sec as u64) {
+                    Ok(()) => Ok(self.body),
+                    Err(err) => {
+                        warn!("rejecting Slack request: {:?}", err);
+                        Err(MyError::http_error(status::Unauthorized, "invalid signature"))
+                    }
+                }
+            }
+            _ => Err(MyError::http_error(status::Unauthorized, "missing signature")),
+        }
+    }
+}
+
+/// Answers slash commands, through the client given by `main` so that tests can use a mock.
+pub struct ReceiveCommand {
+    signing_secret: String,
+    client: Arc<SlackClient>,
+    nlp_backend: Arc<NlpBackend>,
+}
+impl ReceiveCommand {
+    pub fn new(signing_secret: String, client: Arc<SlackClient>, nlp_backend: Arc<NlpBackend>) -> ReceiveCommand {
+        ReceiveCommand {
+            signing_secret,
+            client,
+            nlp_backend,
+        }
+    }
+
+    fn receive(&self, request: SignedBody) -> IronResult<Response> {
+        let body = match request.verified(&self.signing_secret) {
+            Ok(body) => body,
+            Err(response) => return response,
+        };
+        let command = match SlashCommand::from_form(&body) {
+            Some(command) => command,
+            None => return MyError::http_error(status::BadRequest, "invalid slash command"),
+        };
+        let context_identifier = context_identifier(&command.team_id, &command.channel_id);
+        let akc_access_token = {
+            let locked = DATABASE.lock().unwrap();
+            locked.get_token(context_identifier.clone()).cloned()
+        };
+        match akc_access_token {
+            Some(akc_access_token) => {
+                let response_url = command.response_url.clone();
+                let client = self.client.clone();
+                let delivery = answer(context_identifier,
+                                      command.channel_id.clone(),
+                                      akc_access_token,
+                                      &command.text,
+                                      self.client.clone(),
+                                      &*self.nlp_backend)
+                        .and_then(move |message| {
+                                      client
+                                          .respond(&response_url, &message)
+                                          .map_err(|err| warn!("error answering Slack command: {:?}", err))
+                                  });
+                CPU_POOL.spawn(delivery).forget();
+                Ok(Response::with((status::Ok, "")))
+            }
+            None => Ok(Response::with((status::Ok, serde_json::to_string(&signin_message(&context_identifier)).unwrap()))),
+        }
+    }
+}
+impl Handler for ReceiveCommand {
+    fn handle(&self, req: &mut Request) -> IronResult<Response> {
+        match SignedBody::from(req) {
+            Ok(request) => self.receive(request),
+            Err(response) => response,
+        }
+    }
+}
+
+/// Answers mentions of the bot, through the client given by `main` so that tests can use a mock.
+pub struct ReceiveEvent {
+    signing_secret: String,
+    client: Arc<SlackClient>,
+    nlp_backend: Arc<NlpBackend>,
+}
+impl ReceiveEvent {
+    pub fn new(signing_secret: String, client: Arc<SlackClient>, nlp_backend: Arc<NlpBackend>) -> ReceiveEvent {
+        ReceiveEvent {
+            signing_secret,
+            client,
+            nlp_backend,
+        }
+    }
+
+    fn receive(&self, request: SignedBody) -> IronResult<Response> {
+        let body = match request.verified(&self.signing_secret) {
+            Ok(body) => body,
+            Err(response) => return response,
+        };
+        match serde_json::from_str::<EventRequest>(&body) {
+            Ok(EventRequest::UrlVerification { challenge }) => {
+                Ok(Response::with((status::Ok, serde_json::to_string(&Challenge { challenge }).unwrap())))
+            }
+            Ok(EventRequest::EventCallback { team_id, event }) => {
+                match (event.event_type.as_ref(), event.channel, event.text, event.bot_id) {
+                    ("app_mention", Some(channel), Some(text), None) => {
+                        // mentions start with the bot's user id, formatted as <@U12345>
+                        let text = match text.find('>') {
+                            Some(end) if text.starts_with("<@") => text[end + 1..].trim().to_string(),
+                            _ => text.trim().to_string(),
+                        };
+                        let context_identifier = context_identifier(&team_id, &channel);
+                        let akc_access_token = {
+                            let locked = DATABASE.lock().unwrap();
+                            locked.get_token(context_identifier.clone()).cloned()
+                        };
+                        let message = match akc_access_token {
+                            Some(akc_access_token) => {
+                                answer(context_identifier.clone(),
+                                       channel.clone(),
+                                       akc_access_token,
+                                       &text,
+                                       self.client.clone(),
+                                       &*self.nlp_backend)
+                            }
+                            None => ::futures::future::ok(signin_message(&context_identifier)).boxed(),
+                        };
+                        let client = self.client.clone();
+                        let delivery = message.and_then(move |mut message| {
+                                                            message.channel = Some(channel);
+                                                            message.response_type = None;
+                                                            client
+                                                                .post_message(&message)
+                                                                .map_err(|err| warn!("error posting Slack message: {:?}", err))
+                                                        });
+                        CPU_POOL.spawn(delivery).forget();
+                        Ok(Response::with((status::Ok, "")))
+                    }
+                    _ => Ok(Response::with((status::Ok, ""))),
+                }
+            }
+            Err(err) => {
+                MyError::http_error(status::BadRequest,
+                                    &format!("invalid JSON: {:?}", err).to_string())
+            }
+        }
+    }
+}
+impl Handler for ReceiveEvent {
+    fn handle(&self, req: &mut Request) -> IronResult<Response> {
+        match SignedBody::from(req) {
+            Ok(request) => self.receive(request),
+            Err(response) => response,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    use iron::{status, Response};
+    use futures::Future;
+    use time;
+
+    use clients::slack::mock::MockSlackClient;
+    use clients::slack::*;
+    use sami;
+    use oauth2;
+
+    use DATABASE;
+
+    use super::{slack_message_from, context_identifier, SignedBody, ReceiveCommand, ReceiveEvent};
+
+    const SECRET: &'static str = "8f742231b10e8888abcd99yyyzzz85a5";
+
+    fn signed(secret: &str, body: &str) -> SignedBody {
+        let timestamp = time::get_time().sec.to_string();
+        SignedBody {
+            signature: Some(sign(secret, &timestamp, body)),
+            timestamp: Some(timestamp),
+            body: body.to_string(),
+        }
+    }
+
+    fn body_of(response: Response) -> String {
+        let mut body = vec![];
+        let mut written = response.body.unwrap();
+        written.write_body(&mut body).unwrap();
+        String::from_utf8(body).unwrap()
+    }
+
+    fn authenticate(team_id: &str, channel_id: &str) {
+        DATABASE
+            .lock()
+            .unwrap()
+            .add_token(context_identifier(team_id, channel_id),
+                       oauth2::Token::from_access_token("token".to_string()));
+    }
+
+    /// Messages are sent from the pool once the handler answered.
+    fn wait_for_messages(client: &MockSlackClient, count: usize) -> Vec<(Option<String>, SlackMessage)> {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        loop {
+            let sent = client.sent.lock().unwrap().clone();
+            if sent.len() >= count || Instant::now() > deadline {
+                return sent;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    fn handlers() -> (Arc<MockSlackClient>, ReceiveCommand, ReceiveEvent) {
+        let client = Arc::new(MockSlackClient::new());
+        let nlp_backend = Arc::new(sami::rules::Rules::new());
+        (client.clone(),
+         ReceiveCommand::new(SECRET.to_string(), client.clone(), nlp_backend.clone()),
+         ReceiveEvent::new(SECRET.to_string(), client, nlp_backend))
+    }
+
+    #[test]
+    fn rejects_unsigned_requests() {
+        let (client, command, event) = handlers();
+        let body = "team_id=T0&channel_id=C0&user_id=U0&command=%2Fsami&text=log+out&response_url=https%3A%2F%2Fhooks.slack.com%2F0";
+        let forged = command.receive(signed("not the secret", body)).unwrap();
+        assert_eq!(forged.status, Some(status::Unauthorized));
+        let unsigned = event
+            .receive(SignedBody {
+                         timestamp: None,
+                         signature: None,
+                         body: r#"{"type":"url_verification","challenge":"abc"}"#.to_string(),
+                     })
+            .unwrap();
+        assert_eq!(unsigned.status, Some(status::Unauthorized));
+        assert!(client.sent.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn answers_command_through_response_url() {
+        let (client, command, _) = handlers();
+        authenticate("T1", "C1");
+        let body = "team_id=T1&channel_id=C1&user_id=U1&command=%2Fsami&text=list+alerts&response_url=https%3A%2F%2Fhooks.slack.com%2F1";
+        let response = command.receive(signed(SECRET, body)).unwrap();
+        assert_eq!(response.status, Some(status::Ok));
+        let sent = wait_for_messages(&client, 1);
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].0, Some("https://hooks.slack.com/1".to_string()));
+        assert_eq!(sent[0].1.response_type, Some(ResponseType::InChannel));
+    }
+
+    #[test]
+    fn answers_url_verification() {
+        let (_, _, event) = handlers();
+        let response = event
+            .receive(signed(SECRET, r#"{"type":"url_verification","challenge":"abc"}"#))
+            .unwrap();
+        assert_eq!(response.status, Some(status::Ok));
+        assert_eq!(body_of(response), r#"{"challenge":"abc"}"#);
+    }
+
+    #[test]
+    fn answers_mention_in_channel() {
+        let (client, _, event) = handlers();
+        authenticate("T2", "C2");
+        let body = concat!(r#"{"type":"event_callback","team_id":"T2","#,
+                           r#""event":{"type":"app_mention","channel":"C2","user":"U2","text":"<@U0BOT> log out"}}"#);
+        let response = event.receive(signed(SECRET, body)).unwrap();
+        assert_eq!(response.status, Some(status::Ok));
+        let sent = wait_for_messages(&client, 1);
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].0, None);
+        assert_eq!(sent[0].1.channel, Some("C2".to_string()));
+        assert!(DATABASE.lock().unwrap().get_token(context_identifier("T2", "C2")).is_none());
+    }
+
+    #[test]
+    fn renders_and_delivers_field_value() {
+        let message = sami::output::MessageToUser {
+            intent: sami::Intent::GetField,
+            payload: sami::output::Payload::FieldValue(sami::output::FieldReading {
+                                                           device: "Kitchen <sensor>".to_string(),
+                                                           field: "temperature".to_string(),
+                                                           value: "21.5".to_string(),
+                                                           unit: None,
+                                                           ts: None,
+                                                           stale: false,
+                                                       }),
+            status: sami::output::Status::Info,
+        };
+        let client = MockSlackClient::new();
+        client
+            .respond("https://hooks.slack.com/commands/1", &slack_message_from(message))
+            .wait()
+            .unwrap();
+        let sent = client.sent.lock().unwrap();
+        assert_eq!(sent[0].1.blocks,
+                   vec![Block::section(":information_source: Kitchen &lt;sensor&gt;'s `temperature` is *21.5*.")]);
+    }
+}
@@ -18,6 +18,7 @@ extern crate bodyparser;
 
 extern crate hyper;
 extern crate hyper_native_tls;
+extern crate url;
 extern crate futures;
 extern crate futures_cpupool;
 
@@ -26,6 +27,7 @@ extern crate ini;
 #[macro_use]
 extern crate lazy_static;
 extern crate transient_hashmap;
+extern crate regex;
 
 extern crate oauth2;
 extern crate akc;
@@ -36,8 +38,12 @@ mod middlewares;
 mod handlers;
 mod clients;
 mod sami;
+mod storage;
+mod poller;
+mod timer;
 
 use std::env;
+use std::process;
 use iron::prelude::Chain;
 use iron::Iron;
 use router::Router;
@@ -46,14 +52,19 @@ use log::{LogRecord, LogLevelFilter};
 use env_logger::LogBuilder;
 use transient_hashmap::TransientHashMap;
 
-use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
 use futures_cpupool::CpuPool;
 use ini::Ini;
 
+struct SlackConfiguration {
+    signing_secret: String,
+    bot_token: String,
+}
+
 struct Configuration {
     self_url: String,
 
+    nlp_backend: String,
     witai_token: String,
     witai_version: String,
 
@@ -61,6 +72,16 @@ struct Configuration {
     akc_appsecret: String,
 
     hipchat_command: String,
+    /// Hosts HipChat installations may point to, as capabilities documents come from unauthenticated requests.
+    hipchat_hosts: Vec<String>,
+
+    slack: Option<SlackConfiguration>,
+
+    stale_reading_after: u64,
+
+    alert_poll_every: u64,
+
+    storage_path: Option<String>,
 }
 
 lazy_static! {
@@ -71,9 +92,16 @@ lazy_static! {
         let self_section = conf.section(Some("Self".to_owned())).unwrap();
         let self_url = self_section.get("url").unwrap();
 
-        let witai_section = conf.section(Some("WitAI".to_owned())).unwrap();
-        let witai_token = witai_section.get("token").unwrap();
-        let witai_version = witai_section.get("version").unwrap();
+        let nlp_backend = conf.section(Some("NLP".to_owned()))
+            .and_then(|nlp_section| nlp_section.get("backend"))
+            .cloned()
+            .unwrap_or_else(|| "witai".to_owned());
+
+        let (witai_token, witai_version) = match conf.section(Some("WitAI".to_owned())) {
+            Some(witai_section) => (witai_section.get("token").unwrap().to_owned(), witai_section.get("version").unwrap().to_owned()),
+            None if nlp_backend != "witai" => ("".to_owned(), "".to_owned()),
+            None => panic!("missing WitAI section in configuration"),
+        };
 
         let akc_section = conf.section(Some("AKC".to_owned())).unwrap();
         let akc_appid = akc_section.get("appId").unwrap();
@@ -81,35 +109,94 @@ lazy_static! {
 
         let hipchat_section = conf.section(Some("HipChat".to_owned())).unwrap();
         let hipchat_command = hipchat_section.get("command").unwrap();
+        let hipchat_hosts = hipchat_section
+            .get("hosts")
+            .map(|hosts| hosts.split(',').map(|host| host.trim().to_lowercase()).collect())
+            .unwrap_or_else(|| vec!["api.hipchat.com".to_owned()]);
+
+        let slack = conf.section(Some("Slack".to_owned())).map(|slack_section| {
+            SlackConfiguration {
+                signing_secret: slack_section.get("signingSecret").unwrap().to_owned(),
+                bot_token: slack_section.get("botToken").unwrap().to_owned(),
+            }
+        });
+
+        let stale_reading_after = conf.section(Some("Readings".to_owned()))
+            .and_then(|readings_section| readings_section.get("staleAfter"))
+            .map(|stale_after| stale_after.parse::<u64>().expect("staleAfter should be a number of seconds"))
+            .unwrap_or(60 * 60 * 24);
+
+        let alert_poll_every = conf.section(Some("Alerts".to_owned()))
+            .and_then(|alerts_section| alerts_section.get("pollEvery"))
+            .map(|poll_every| poll_every.parse::<u64>().expect("pollEvery should be a number of seconds"))
+            .unwrap_or(60);
+
+        let storage_path = conf.section(Some("Storage".to_owned()))
+            .and_then(|storage_section| storage_section.get("path"))
+            .cloned();
         Configuration {
             self_url: self_url.to_owned(),
-            witai_token: witai_token.to_owned(),
-            witai_version: witai_version.to_owned(),
+            nlp_backend,
+            witai_token,
+            witai_version,
             akc_appid: akc_appid.to_owned(),
             akc_appsecret: akc_appsecret.to_owned(),
             hipchat_command: hipchat_command.to_owned(),
+            hipchat_hosts,
+            slack,
+            stale_reading_after,
+            alert_poll_every,
+            storage_path,
+        }
+    };
+}
+
+lazy_static! {
+    static ref NLP_BACKEND: Arc<sami::input::NlpBackend> = {
+        match CONFIGURATION.nlp_backend.as_ref() {
+            "witai" => Arc::new(clients::witai::WitAi {}),
+            "rules" => Arc::new(sami::rules::Rules::new()),
+            other => panic!("unknown NLP backend {:?}", other),
         }
     };
 }
 
+lazy_static! {
+    static ref CPU_POOL: CpuPool = CpuPool::new_num_cpus();
+}
+
 lazy_static! {
     static ref USER_CACHE: Arc<Mutex<TransientHashMap<String, akc::user::User>>> = {
         Arc::new(Mutex::new(TransientHashMap::new(60 * 60 * 24 * 14)))
     };
 }
+lazy_static! {
+    static ref REFRESHED_TOKENS: Arc<Mutex<TransientHashMap<String, oauth2::Token>>> = {
+        Arc::new(Mutex::new(TransientHashMap::new(60 * 10)))
+    };
+}
+lazy_static! {
+    static ref DEVICE_TYPE_CACHE: Arc<Mutex<TransientHashMap<String, Vec<akc::device_type::DeviceType>>>> = {
+        Arc::new(Mutex::new(TransientHashMap::new(60 * 60 * 6)))
+    };
+}
+lazy_static! {
+    static ref CONVERSATIONS: Arc<Mutex<TransientHashMap<String, sami::conversation::Conversation>>> = {
+        Arc::new(Mutex::new(TransientHashMap::new(60 * 15)))
+    };
+}
 lazy_static! {
     static ref DEVICE_CACHE: Arc<Mutex<TransientHashMap<String, Vec<akc::device::Device>>>> = {
         Arc::new(Mutex::new(TransientHashMap::new(60 * 30)))
     };
 }
 
-#[derive(Clone, Debug)]
 pub struct Database {
-    tokens: HashMap<String, oauth2::Token>,
+    tokens: Box<storage::Storage<oauth2::Token>>,
 }
 impl Database {
-    pub fn new() -> Database {
-        Database { tokens: HashMap::new() }
+    pub fn new(tokens: Box<storage::Storage<oauth2::Token>>) -> Database {
+        Database { tokens }
     }
     pub fn add_token(&mut self, from: String, token: oauth2::Token) {
         info!("setting token {} - {:?}", from, token);
@@ -118,20 +205,47 @@ impl Database {
     pub fn get_token(&self, key: String) -> Option<&oauth2::Token> {
         self.tokens.get(&key)
     }
-    pub fn remove_token(&mut self, access_token: String) {
+    pub fn replace_token(&mut self, access_token: &str, token: oauth2::Token) {
+        let keys_to_update: Vec<String> = self.tokens
+            .keys()
+            .into_iter()
+            .filter(|key| self.tokens.get(key).map(|v| v.access_token() == access_token).unwrap_or(false))
+            .collect();
+        for key in keys_to_update {
+            info!("refreshing token {} - {:?}", key, token);
+            self.tokens.insert(key, token.clone());
+        }
+    }
+    pub fn remove_tokens_with_prefix(&mut self, prefix: &str) {
         let keys_to_remove: Vec<String> = self.tokens
-            .clone()
-            .iter()
-            .filter(|&(_, v)| v.access_token() == access_token)
-            .map(|(k, _)| k.clone())
+            .keys()
+            .into_iter()
+            .filter(|key| key.starts_with(prefix))
             .collect();
         for key in keys_to_remove {
+            info!("removing token {}", key);
             self.tokens.remove(&key);
         }
     }
+    pub fn remove_token(&mut self, key: &str) {
+        info!("removing token {}", key);
+        self.tokens.remove(key);
+    }
 }
 lazy_static! {
-    static ref DATABASE: Arc<Mutex<Database>> = Arc::new(Mutex::new(Database::new()));
+    /// Kept in memory until `main` opens the storage.
+    static ref DATABASE: Arc<Mutex<Database>> = Arc::new(Mutex::new(Database::new(Box::new(storage::MemoryStorage::new()))));
+}
+
+/// Opens every storage before serving, so that an unreadable file stops the bot instead of a request.
+fn open_storages() -> Result<(), String> {
+    let tokens = storage::open("akc_tokens")?;
+    let installations = storage::open("hipchat_installations")?;
+    let alerts = storage::open("alerts")?;
+    *DATABASE.lock().unwrap() = Database::new(tokens);
+    *handlers::hipchat::HC_DATABASE.lock().unwrap() = handlers::hipchat::Database::new(installations);
+    *sami::alerts::ALERTS.lock().unwrap() = sami::alerts::Alerts::new(alerts);
+    Ok(())
 }
 
 fn main() {
@@ -156,6 +270,11 @@ fn main() {
 
     info!("application starting");
 
+    if let Err(err) = open_storages() {
+        error!("{}", err);
+        process::exit(1);
+    }
+
     let (logger_before, logger_after) = Logger::new(None);
 
     let mut router = Router::new();
@@ -176,6 +295,22 @@ fn main() {
                   handlers::hipchat::installation_callback::DeleteInstallation::new(),
                   "hipchat_installation_delete");
 
+    let slack_client = CONFIGURATION
+        .slack
+        .as_ref()
+        .map(|slack| Arc::new(clients::slack::SlackApi::new(slack.bot_token.to_owned())) as Arc<clients::slack::SlackClient>);
+    if let (Some(slack), Some(slack_client)) = (CONFIGURATION.slack.as_ref(), slack_client.as_ref()) {
+        info!("url for slack commands: {}/slack/command, events: {}/slack/events",
+              &CONFIGURATION.self_url,
+              &CONFIGURATION.self_url);
+        router.post("/slack/command",
+                    handlers::slack::ReceiveCommand::new(slack.signing_secret.to_owned(), slack_client.clone(), NLP_BACKEND.clone()),
+                    "slack_command");
+        router.post("/slack/events",
+                    handlers::slack::ReceiveEvent::new(slack.signing_secret.to_owned(), slack_client.clone(), NLP_BACKEND.clone()),
+                    "slack_events");
+    }
+
     router.get("/akc/auth",
                handlers::akc::ExchangeToken::new(),
                "akc_exchange_token");
@@ -198,6 +333,8 @@ fn main() {
 
 
 
+    poller::start(slack_client);
+
     let mut chain = Chain::new(router);
     chain.link_before(logger_before);
     chain.link_after(middlewares::Default404);
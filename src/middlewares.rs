@@ -40,6 +40,7 @@ impl AfterMiddleware for ErrorLogger {
         Ok(match res.status {
                Some(status::Ok) |
                Some(status::Created) |
+               Some(status::NoContent) |
                None => res,
                Some(other) => {
                    let mut body: Vec<u8> = Vec::new();
@@ -0,0 +1,73 @@
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use futures::future::{self, Future};
+
+use sami;
+use sami::alerts::ALERTS;
+use handlers;
+use clients::slack::SlackClient;
+
+use CONFIGURATION;
+use CPU_POOL;
+
+lazy_static! {
+    /// Rooms whose alerts are being checked, not to check them again before the previous check is done.
+    static ref CHECKING: Mutex<HashSet<String>> = Mutex::new(HashSet::new());
+}
+
+/// Periodically checks the alerts of every room on the shared pool.
+pub fn start(slack_client: Option<Arc<SlackClient>>) {
+    info!("checking alerts every {} seconds", CONFIGURATION.alert_poll_every);
+    thread::spawn(move || loop {
+                      thread::sleep(Duration::from_secs(CONFIGURATION.alert_poll_every));
+                      let contexts = ALERTS.lock().unwrap().contexts();
+                      for context in contexts {
+                          if !CHECKING.lock().unwrap().insert(context.clone()) {
+                              info!("alerts of {} are still being checked", context);
+                              continue;
+                          }
+                          CPU_POOL.spawn(check(context, slack_client.clone())).forget();
+                      }
+                  });
+}
+
+fn check(context: String, slack_client: Option<Arc<SlackClient>>) -> Box<Future<Item = (), Error = ()> + Send> {
+    let checked = context.clone();
+    future::lazy(move || {
+                     let deliveries = sami::alerts::check(&context)
+                         .into_iter()
+                         .map(|message| deliver(&context, message, slack_client.as_ref()))
+                         .collect::<Vec<Box<Future<Item = (), Error = ()> + Send>>>();
+                     future::join_all(deliveries).map(|_| ())
+                 })
+            .then(move |result| {
+                      CHECKING.lock().unwrap().remove(&checked);
+                      result
+                  })
+            .boxed()
+}
+
+/// Sends a message to the room identified by `context`, as built by the chat handlers.
+pub fn deliver(context: &str,
+               message: sami::output::MessageToUser,
+               slack_client: Option<&Arc<SlackClient>>)
+               -> Box<Future<Item = (), Error = ()> + Send> {
+    if context.starts_with("hipchatroom-") {
+        // the oauth id of the installation contains dashes, the room id does not
+        let installation_and_room = &context["hipchatroom-".len()..];
+        if let Some(separator) = installation_and_room.rfind('-') {
+            if let Ok(room_id) = installation_and_room[separator + 1..].parse::<u32>() {
+                return handlers::hipchat::notification::notify_room(&installation_and_room[..separator], room_id, message);
+            }
+        }
+    } else if context.starts_with("slackchannel-") {
+        if let (Some(slack_client), Some(separator)) = (slack_client, context.rfind('-')) {
+            return handlers::slack::notify_channel(&**slack_client, context[separator + 1..].to_string(), message);
+        }
+    }
+    warn!("don't know how to notify {:?}", context);
+    future::err(()).boxed()
+}
@@ -1,14 +1,25 @@
-use std::collections::HashMap;
+use std::cmp::Reverse;
+use std::time::Duration;
 
 use futures::Future;
+use serde_json;
 
 use oauth2;
 use akc;
 
 use sami::Error;
+use sami::input::Period;
+use sami::matching::{self, Matches};
 
 use USER_CACHE;
 use DEVICE_CACHE;
+use DEVICE_TYPE_CACHE;
+use REFRESHED_TOKENS;
+use DATABASE;
+use CONFIGURATION;
+
+/// How many names are suggested when no device matches.
+const SUGGESTED_DEVICES: usize = 3;
 
 #[derive(Debug)]
 pub struct FieldValueAndPath {
@@ -17,6 +28,13 @@ pub struct FieldValueAndPath {
     pub value: akc::snapshot::FieldValue,
     pub ts: Option<u64>,
 }
+impl FieldValueAndPath {
+    pub fn full_name(&self) -> String {
+        let mut full_path = self.path.clone();
+        full_path.push(self.name.clone());
+        full_path.join(".")
+    }
+}
 impl Clone for FieldValueAndPath {
     fn clone(&self) -> FieldValueAndPath {
         FieldValueAndPath {
@@ -47,7 +65,7 @@ macro_rules! cache_get_or_set {
                     },
                     Err(err) => {
                         warn!("Error: {:?}", err);
-                        return Err(Error::AkcError);
+                        return Err(Error::from(err));
                     }
                 }
             }
@@ -55,42 +73,205 @@ macro_rules! cache_get_or_set {
     }
 }
 
+/// Returns the most recent token for `akc_token`, refreshing it if it is about to expire.
+pub fn current_token(akc_token: &oauth2::Token) -> oauth2::Token {
+    let refreshed = {
+        let mut lock = REFRESHED_TOKENS.lock().unwrap();
+        lock.get(&akc_token.access_token().to_string()).cloned()
+    };
+    let token = refreshed.unwrap_or_else(|| akc_token.clone());
+    if token.expires_within(Duration::from_secs(60)) {
+        refresh_token(&token).unwrap_or(token)
+    } else {
+        token
+    }
+}
+
+fn refresh_token(akc_token: &oauth2::Token) -> Result<oauth2::Token, Error> {
+    let refreshed = oauth2::Oauth2::new(CONFIGURATION.akc_appid.to_owned(),
+                                        CONFIGURATION.akc_appsecret.to_owned(),
+                                        akc::Akc::token_url())
+            .unwrap()
+            .refresh(akc_token);
+    match refreshed {
+        Ok(token) => {
+            DATABASE
+                .lock()
+                .unwrap()
+                .replace_token(akc_token.access_token(), token.clone());
+            REFRESHED_TOKENS
+                .lock()
+                .unwrap()
+                .insert(akc_token.access_token().to_string(), token.clone());
+            Ok(token)
+        }
+        Err(err) => {
+            warn!("Error refreshing token: {:?}", err);
+            Err(Error::AkcError)
+        }
+    }
+}
+
+/// Calls AKC with a valid token, refreshing it once and retrying if AKC answers 401.
+fn call_with_refresh<T, F>(akc_token: &oauth2::Token, call: F) -> Result<T, akc::error::AkcClientError>
+    where F: Fn(oauth2::Token) -> Box<Future<Item = T, Error = akc::error::AkcClientError>>
+{
+    let token = current_token(akc_token);
+    match call(token.clone()).wait() {
+        Err(akc::error::AkcClientError::AkcError(401, message)) => {
+            match refresh_token(&token) {
+                Ok(token) => call(token).wait(),
+                Err(_) => Err(akc::error::AkcClientError::AkcError(401, message)),
+            }
+        }
+        result => result,
+    }
+}
 
 pub fn find_user(akc_token: &oauth2::Token) -> Result<akc::user::User, Error> {
     Ok(cache_get_or_set!(USER_CACHE,
                          akc_token.access_token().to_string(),
-                         akc::Akc::user_self(akc_token.clone()).wait()))
+                         call_with_refresh(akc_token, |token| akc::Akc::user_self(token))))
+}
+
+pub fn find_devices(akc_token: &oauth2::Token) -> Result<Vec<akc::device::Device>, Error> {
+    let uid = find_user(akc_token)?.id;
+    Ok(cache_get_or_set!(DEVICE_CACHE,
+                         akc_token.access_token().to_string(),
+                         call_with_refresh(akc_token, |token| akc::Akc::devices_parallel(token, &uid))))
+}
+
+/// Devices matching the name and device type indications if any, sorted by name.
+pub fn find_devices_matching(akc_token: &oauth2::Token,
+                             indications: Option<&[String]>,
+                             device_type_indication: Option<&str>)
+                             -> Result<Vec<akc::device::Device>, Error> {
+    let device_types = match device_type_indication {
+        Some(indication) => {
+            match find_device_types_with(akc_token, &indication.to_lowercase()) {
+                Ok(device_types) => Some(device_types),
+                Err(Error::NoMatch) => Some(vec![]),
+                Err(err) => return Err(err),
+            }
+        }
+        None => None,
+    };
+    let mut devices = find_devices(akc_token)?
+        .into_iter()
+        .filter(|device| indications.map(|indications| matching::score(&device.name, indications) > 0).unwrap_or(true))
+        .filter(|device| {
+                    device_types
+                        .as_ref()
+                        .map(|device_types| device_types.iter().any(|device_type| device_type.id == device.dtid))
+                        .unwrap_or(true)
+                })
+        .collect::<Vec<akc::device::Device>>();
+    devices.sort_by_key(|device| device.name.to_lowercase());
+    Ok(devices)
+}
+
+/// The device type `dtid`, among the device types visible to the user.
+pub fn find_device_type(akc_token: &oauth2::Token, dtid: &str) -> Result<akc::device_type::DeviceType, Error> {
+    find_device_types(akc_token)?
+        .into_iter()
+        .find(|device_type| device_type.id == dtid)
+        .ok_or(Error::NoMatch)
 }
 
 pub fn find_device_with(akc_token: &oauth2::Token, indications: &[String]) -> Result<akc::device::Device, Error> {
+    let devices = find_devices(akc_token)?;
+    let scored = devices
+        .into_iter()
+        .map(|device| (matching::score(&device.name, indications), device))
+        .filter(|&(score, _)| score > 0)
+        .collect::<Vec<(u32, akc::device::Device)>>();
+    match matching::best(scored) {
+        Matches::Nothing => Err(Error::NoMatch),
+        Matches::One(device) => Ok(device),
+        Matches::Ambiguous(devices) => Err(Error::Ambiguous(devices.into_iter().map(|device| device.name).collect())),
+    }
+}
+
+/// Names of the devices closest to `indications`, closest first, to suggest when none matched.
+pub fn find_closest_devices(akc_token: &oauth2::Token, indications: &[String]) -> Result<Vec<String>, Error> {
+    let mut scored = find_devices(akc_token)?
+        .into_iter()
+        .map(|device| (matching::closeness(&device.name, indications), device.name))
+        .filter(|&(closeness, _)| closeness > 0)
+        .collect::<Vec<(u32, String)>>();
+    scored.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.cmp(&b.1)));
+    Ok(scored
+           .into_iter()
+           .take(SUGGESTED_DEVICES)
+           .map(|(_, name)| name)
+           .collect())
+}
+
+/// Device types visible to the user, kept per user as they include their private device types.
+pub fn find_device_types(akc_token: &oauth2::Token) -> Result<Vec<akc::device_type::DeviceType>, Error> {
     let uid = find_user(akc_token)?.id;
-    let mut devices = cache_get_or_set!(DEVICE_CACHE,
-                                        akc_token.access_token().to_string(),
-                                        akc::Akc::devices_parallel(akc_token.clone(), &uid).wait());
-    for indication in indications {
-        devices = devices
-            .iter()
-            .filter(|device| device.name.to_lowercase().contains(indication))
-            .cloned()
-            .collect::<Vec<akc::device::Device>>();
+    Ok(cache_get_or_set!(DEVICE_TYPE_CACHE,
+                         uid,
+                         call_with_refresh(akc_token, |token| akc::Akc::device_types_parallel(token))))
+}
+
+pub fn find_device_types_with(akc_token: &oauth2::Token, indication: &str) -> Result<Vec<akc::device_type::DeviceType>, Error> {
+    let mut scored = find_device_types(akc_token)?
+        .into_iter()
+        .map(|device_type| (device_type_score(&device_type, indication), device_type))
+        .filter(|&(score, _)| score > 0)
+        .collect::<Vec<(u32, akc::device_type::DeviceType)>>();
+    scored.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.name.len().cmp(&b.1.name.len())));
+    match scored.len() {
+        0 => Err(Error::NoMatch),
+        _ => Ok(scored.into_iter().map(|(_, device_type)| device_type).collect()),
+    }
+}
+
+fn device_type_score(device_type: &akc::device_type::DeviceType, indication: &str) -> u32 {
+    let name = device_type.name.to_lowercase();
+    let unique_name = device_type.unique_name.to_lowercase();
+    let words = indication.split_whitespace().collect::<Vec<&str>>();
+    if words.is_empty() {
+        return 0;
+    }
+    if name == indication {
+        return 100;
     }
-    match devices.get(0).cloned() {
-        Some(device) => Ok(device),
-        None => Err(Error::NoMatch),
+    let mut score = 0;
+    for word in &words {
+        if name.split(|c: char| !c.is_alphanumeric()).any(|name_word| name_word == *word) {
+            score += 10;
+        } else if name.contains(word) {
+            score += 5;
+        } else if unique_name.contains(word) {
+            score += 2;
+        } else {
+            return 0;
+        }
     }
+    if name.starts_with(words[0]) {
+        score += 5;
+    }
+    score
 }
 
-pub fn find_field_value_with(akc_token: &oauth2::Token,
-                             device_id: &str,
-                             field_indication: &str)
-                             -> Result<FieldValueAndPath, Error> {
-    let snapshots = match akc::Akc::snapshots(akc_token.clone(), vec![device_id.to_string()]).wait() {
+pub fn find_snapshots(akc_token: &oauth2::Token, device_ids: Vec<String>) -> Result<Vec<akc::snapshot::Snapshot>, Error> {
+    call_with_refresh(akc_token, |token| akc::Akc::snapshots(token, device_ids.clone())).map_err(|err| {
+        warn!("Error getting snapshots for devices {:?}: {:?}", device_ids, err);
+        Error::AkcError
+    })
+}
+
+/// Every field of the latest snapshot of a device, sorted by path.
+pub fn find_field_values(akc_token: &oauth2::Token, device_id: &str) -> Result<Vec<FieldValueAndPath>, Error> {
+    let snapshots = match call_with_refresh(akc_token, |token| akc::Akc::snapshots(token, vec![device_id.to_string()])) {
         Ok(snapshots) => snapshots,
         Err(err) => {
             warn!("Error getting snapshot for device {:?}: {:?}",
                   device_id,
                   err);
-            return Err(Error::AkcError);
+            return Err(Error::from(err));
         }
     };
     let snapshot = match snapshots.get(0) {
@@ -98,19 +279,26 @@ pub fn find_field_value_with(akc_token: &oauth2::Token,
         None => {
             warn!("Error getting snapshot for device {:?}: no result",
                   device_id);
-            return Err(Error::AkcError);
+            return Err(Error::NoMatch);
         }
     };
-    if let akc::snapshot::FieldData::Group(root) = snapshot.data {
-        let mut fields = recur_find_fields(&root, vec![], field_indication);
-        match fields.len() {
-            0 => Err(Error::NoMatch),
-            _ => {
-                info!("fields found: {:?}", fields);
-                fields.sort_by(|a, b| a.path.len().cmp(&b.path.len()));
-                Ok(fields[0].clone())
-            }
-        }
+    if let akc::snapshot::FieldData::Group(_) = snapshot.data {
+        let mut fields = snapshot
+            .data
+            .fields()
+            .into_iter()
+            .map(|(mut path, ts, value)| {
+                     let name = path.pop().unwrap_or_default();
+                     FieldValueAndPath {
+                         path: path,
+                         name: name,
+                         value: value.clone(),
+                         ts: ts,
+                     }
+                 })
+            .collect::<Vec<FieldValueAndPath>>();
+        fields.sort_by_key(|field| field.full_name());
+        Ok(fields)
     } else {
         warn!("Error getting snapshot for device {:?}: no subfields",
               device_id);
@@ -118,32 +306,159 @@ pub fn find_field_value_with(akc_token: &oauth2::Token,
     }
 }
 
-fn recur_find_fields(subfields: &HashMap<String, Box<akc::snapshot::FieldData>>,
-                     path: Vec<String>,
-                     field_indication: &str)
-                     -> Vec<FieldValueAndPath> {
-    let mut result = vec![];
-    for (name, value) in subfields.iter() {
-        info!("{:?} - {:?} : {:?}", path, name, value);
-        match **value {
-            akc::snapshot::FieldData::Field { ts, ref value } => {
-                if name == field_indication {
-                    result.push(FieldValueAndPath {
-                                    path: path.clone(),
-                                    name: name.to_owned(),
-                                    value: value.to_owned(),
-                                    ts,
-                                });
-                }
-            }
-            akc::snapshot::FieldData::Group(ref subfields) => {
-                let mut new_path = path.clone();
-                new_path.push(name.to_owned());
-                result.extend(recur_find_fields(subfields, new_path, field_indication));
-            }
+pub fn find_field_value_with(akc_token: &oauth2::Token,
+                             device_id: &str,
+                             field_indication: &str)
+                             -> Result<FieldValueAndPath, Error> {
+    let indications = vec![field_indication.to_string()];
+    // fields named by their path first, then on a tie, fields closer to the root win
+    let scored = find_field_values(akc_token, device_id)?
+        .into_iter()
+        .map(|field| {
+                 let mut path = field.path.clone();
+                 path.push(field.name.clone());
+                 let named = akc::snapshot::path_matches(&path, field_indication);
+                 ((named, matching::score(&field.full_name(), &indications), Reverse(field.path.len())), field)
+             })
+        .filter(|&((named, score, _), _)| named || score > 0)
+        .collect::<Vec<((bool, u32, Reverse<usize>), FieldValueAndPath)>>();
+    match matching::best(scored) {
+        Matches::Nothing => Err(Error::NoMatch),
+        Matches::One(field) => {
+            info!("field found: {:?}", field);
+            Ok(field)
         }
+        Matches::Ambiguous(fields) => Err(Error::Ambiguous(fields.iter().map(|field| field.full_name()).collect())),
     }
-    result
 }
 
+/// Values of a field during `period`, oldest first, with their time in milliseconds since epoch.
+pub fn find_history(akc_token: &oauth2::Token,
+                    device_id: &str,
+                    field: &FieldValueAndPath,
+                    period: Period)
+                    -> Result<Vec<(u64, serde_json::Value)>, Error> {
+    let mut full_path = field.path.clone();
+    full_path.push(field.name.clone());
+    let full_name = field.full_name();
+    match call_with_refresh(akc_token,
+                            |token| akc::Akc::messages(token, device_id, period.from, period.to, Some(&full_name), 10)) {
+        Ok(messages) => {
+            Ok(messages
+                   .iter()
+                   .filter_map(|message| message.field(&full_path).map(|value| (message.ts, value.clone())))
+                   .collect())
+        }
+        Err(err) => {
+            warn!("Error getting messages for device {:?}: {:?}", device_id, err);
+            Err(Error::from(err))
+        }
+    }
+}
+
+/// Statistics of a numeric field during `period`, `None` if the device sent no value for it.
+pub fn find_aggregate(akc_token: &oauth2::Token,
+                      device_id: &str,
+                      field: &FieldValueAndPath,
+                      period: Period)
+                      -> Result<Option<akc::message::Aggregate>, Error> {
+    let full_name = field.full_name();
+    call_with_refresh(akc_token,
+                      |token| akc::Akc::aggregates(token, device_id, period.from, period.to, &full_name))
+            .map_err(|err| {
+                         warn!("Error getting aggregates for device {:?}: {:?}", device_id, err);
+                         Error::from(err)
+                     })
+}
+
+/// Statistics of a numeric field for each `interval` of `period`, skipping those without value.
+pub fn find_histogram(akc_token: &oauth2::Token,
+                      device_id: &str,
+                      field: &FieldValueAndPath,
+                      period: Period,
+                      interval: akc::message::Interval)
+                      -> Result<Vec<akc::message::Aggregate>, Error> {
+    let full_name = field.full_name();
+    call_with_refresh(akc_token,
+                      |token| akc::Akc::histogram(token, device_id, period.from, period.to, &full_name, interval))
+            .map(|buckets| buckets.into_iter().filter(|bucket| bucket.count > 0).collect())
+            .map_err(|err| {
+                         warn!("Error getting histogram for device {:?}: {:?}", device_id, err);
+                         Error::from(err)
+                     })
+}
+
+pub fn find_manifest(akc_token: &oauth2::Token, dtid: &str) -> Result<akc::manifest::Manifest, Error> {
+    call_with_refresh(akc_token, |token| akc::Akc::manifest_cached(token, dtid)).map_err(|err| {
+        warn!("Error getting manifest of {:?}: {:?}", dtid, err);
+        Error::from(err)
+    })
+}
+
+/// Unit of a field as described by the manifest of the device type, if any.
+pub fn find_unit(akc_token: &oauth2::Token, dtid: &str, field: &FieldValueAndPath) -> Option<String> {
+    let mut full_path = field.path.clone();
+    full_path.push(field.name.clone());
+    find_manifest(akc_token, dtid)
+        .ok()
+        .and_then(|manifest| manifest.unit(&full_path))
+}
+
+/// Whether the manifest describes a field named `field_indication`, even if the device never sent it.
+pub fn is_known_field(akc_token: &oauth2::Token, dtid: &str, field_indication: &str) -> bool {
+    match find_manifest(akc_token, dtid) {
+        Ok(manifest) => {
+            manifest
+                .field_paths()
+                .iter()
+                .any(|path| path.last().map(|name| name == field_indication).unwrap_or(false))
+        }
+        Err(_) => false,
+    }
+}
+
+/// Fields of the manifest close to `field_indication`, or the first fields of the manifest if none are.
+pub fn suggest_fields(akc_token: &oauth2::Token, dtid: &str, field_indication: &str) -> Vec<String> {
+    let field_paths = match find_manifest(akc_token, dtid) {
+        Ok(manifest) => manifest.field_paths(),
+        Err(_) => return vec![],
+    };
+    let indications = vec![field_indication.to_string()];
+    let names = field_paths
+        .iter()
+        .map(|path| path.join("."))
+        .collect::<Vec<String>>();
+    let mut close = names
+        .iter()
+        .map(|name| (matching::closeness(name, &indications), name.clone()))
+        .filter(|&(closeness, _)| closeness > 0)
+        .collect::<Vec<(u32, String)>>();
+    close.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.cmp(&b.1)));
+    if close.is_empty() {
+        names.into_iter().take(5).collect()
+    } else {
+        close.into_iter().take(5).map(|(_, name)| name).collect()
+    }
+}
+
+pub fn set_field_value(akc_token: &oauth2::Token,
+                       device: &akc::device::Device,
+                       field: &FieldValueAndPath,
+                       value: akc::snapshot::FieldValue)
+                       -> Result<(), Error> {
+    // only the actions of the manifest change the device, a message would just record a fake reading
+    let action = match find_manifest(akc_token, &device.dtid)?.setter_action(&field.name, &value) {
+        Some(action) => action,
+        None => {
+            info!("no action to set {} in the manifest of {:?}", field.full_name(), device.dtid);
+            return Err(Error::UnsupportedAction);
+        }
+    };
+    match call_with_refresh(akc_token, |token| akc::Akc::send_action(token, &device.id, action.clone())) {
+        Ok(_) => Ok(()),
+        Err(err) => {
+            warn!("Error sending action to device {:?}: {:?}", device.id, err);
+            Err(Error::from(err))
+        }
+    }
 }
@@ -0,0 +1,268 @@
+use std::sync::{Arc, Mutex};
+
+use oauth2;
+use akc;
+
+use sami::Intent;
+use sami::input::Comparison;
+use sami::output::{MessageToUser, Payload, Status};
+use sami::akc_request;
+use storage::{Storage, MemoryStorage};
+
+use DATABASE;
+
+/// A threshold on a numeric field, checked by the poller for the room that set it.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Alert {
+    pub device_id: String,
+    pub device: String,
+    pub path: Vec<String>,
+    pub unit: Option<String>,
+    pub comparison: Comparison,
+    pub threshold: f64,
+    /// Set while the threshold is crossed, so that the room is only notified once each time.
+    #[serde(default)]
+    pub triggered: bool,
+}
+impl Alert {
+    pub fn field(&self) -> String {
+        self.path.join(".")
+    }
+
+    /// Whether `other` is the same alert, whatever their triggered state.
+    fn is_same_as(&self, other: &Alert) -> bool {
+        self.device_id == other.device_id && self.path == other.path && self.comparison == other.comparison &&
+        self.threshold == other.threshold
+    }
+
+    pub fn is_crossed_by(&self, value: f64) -> bool {
+        match self.comparison {
+            Comparison::Above => value > self.threshold,
+            Comparison::Below => value < self.threshold,
+        }
+    }
+}
+
+pub struct Alerts {
+    alerts: Box<Storage<Vec<Alert>>>,
+}
+impl Alerts {
+    pub fn new(alerts: Box<Storage<Vec<Alert>>>) -> Alerts {
+        Alerts { alerts }
+    }
+    pub fn add(&mut self, context: &str, alert: Alert) {
+        info!("adding alert for {} - {:?}", context, alert);
+        let mut alerts = self.list(context);
+        alerts.push(alert);
+        self.alerts.insert(context.to_string(), alerts);
+    }
+    pub fn list(&self, context: &str) -> Vec<Alert> {
+        self.alerts.get(context).cloned().unwrap_or_default()
+    }
+    /// Removes the alert at `index`, starting from 1 as when listed.
+    pub fn remove(&mut self, context: &str, index: usize) -> Option<Alert> {
+        let mut alerts = self.list(context);
+        if index == 0 || index > alerts.len() {
+            return None;
+        }
+        let removed = alerts.remove(index - 1);
+        if alerts.is_empty() {
+            self.alerts.remove(context);
+        } else {
+            self.alerts.insert(context.to_string(), alerts);
+        }
+        Some(removed)
+    }
+    pub fn remove_contexts_with_prefix(&mut self, prefix: &str) {
+        for context in self.contexts().iter().filter(|context| context.starts_with(prefix)) {
+            info!("removing alerts for {}", context);
+            self.alerts.remove(context);
+        }
+    }
+    pub fn contexts(&self) -> Vec<String> {
+        self.alerts.keys()
+    }
+    fn update_triggered(&mut self, context: &str, checked: &[(Alert, bool)]) {
+        let mut alerts = self.list(context);
+        let mut changed = false;
+        // alerts added since the check are left for the next one
+        for alert in &mut alerts {
+            if let Some(&(_, triggered)) = checked.iter().find(|&&(ref checked, _)| checked.is_same_as(alert)) {
+                changed = changed || alert.triggered != triggered;
+                alert.triggered = triggered;
+            }
+        }
+        if changed {
+            self.alerts.insert(context.to_string(), alerts);
+        }
+    }
+}
+lazy_static! {
+    /// Kept in memory until `main` opens the storage.
+    pub static ref ALERTS: Arc<Mutex<Alerts>> = Arc::new(Mutex::new(Alerts::new(Box::new(MemoryStorage::new()))));
+}
+
+/// Checks the alerts of a room against the latest values of their devices, returning a message for each alert
+/// that has just been crossed.
+pub fn check(context: &str) -> Vec<MessageToUser> {
+    let akc_token = {
+        let locked = DATABASE.lock().unwrap();
+        locked.get_token(context.to_string()).cloned()
+    };
+    let akc_token = match akc_token {
+        Some(akc_token) => akc_token,
+        None => return vec![],
+    };
+    let alerts = ALERTS.lock().unwrap().list(context);
+    if alerts.is_empty() {
+        return vec![];
+    }
+    let snapshots = match latest_values(&akc_token, &alerts) {
+        Some(snapshots) => snapshots,
+        None => return vec![],
+    };
+    let (messages, checked) = evaluate(&alerts, &snapshots);
+    ALERTS
+        .lock()
+        .unwrap()
+        .update_triggered(context, &checked);
+    messages
+}
+
+/// Messages for the alerts that `snapshots` have just crossed, and whether each alert is now triggered.
+fn evaluate(alerts: &[Alert], snapshots: &[akc::snapshot::Snapshot]) -> (Vec<MessageToUser>, Vec<(Alert, bool)>) {
+    let mut messages = vec![];
+    let mut checked = vec![];
+    for alert in alerts {
+        let value = snapshots
+            .iter()
+            .find(|snapshot| snapshot.sdid == alert.device_id)
+            .and_then(|snapshot| match snapshot.data.get(&alert.path) {
+                          Some(&akc::snapshot::FieldData::Field { ref value, .. }) => value.as_f64(),
+                          _ => None,
+                      });
+        match value {
+            Some(value) => {
+                let crossed = alert.is_crossed_by(value);
+                if crossed && !alert.triggered {
+                    messages.push(MessageToUser {
+                                      intent: Intent::CreateAlert,
+                                      payload: Payload::AlertTriggered {
+                                          alert: alert.clone(),
+                                          value: value,
+                                      },
+                                      status: Status::ActionRequired,
+                                  });
+                }
+                checked.push((alert.clone(), crossed));
+            }
+            // without a reading, the alert is left as it was
+            None => checked.push((alert.clone(), alert.triggered)),
+        }
+    }
+    (messages, checked)
+}
+
+fn latest_values(akc_token: &oauth2::Token, alerts: &[Alert]) -> Option<Vec<akc::snapshot::Snapshot>> {
+    let mut device_ids = alerts
+        .iter()
+        .map(|alert| alert.device_id.clone())
+        .collect::<Vec<String>>();
+    device_ids.sort();
+    device_ids.dedup();
+    akc_request::find_snapshots(akc_token, device_ids).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use akc::snapshot::{Snapshot, FieldData, FieldValue};
+
+    use storage::MemoryStorage;
+    use sami::input::Comparison;
+
+    use super::*;
+
+    fn alert(comparison: Comparison, threshold: f64) -> Alert {
+        Alert {
+            device_id: "d1".to_string(),
+            device: "Freezer".to_string(),
+            path: vec!["temperature".to_string()],
+            unit: None,
+            comparison: comparison,
+            threshold: threshold,
+            triggered: false,
+        }
+    }
+
+    #[test]
+    fn compares_with_threshold() {
+        assert!(alert(Comparison::Above, -10.0).is_crossed_by(-5.0));
+        assert!(!alert(Comparison::Above, -10.0).is_crossed_by(-10.0));
+        assert!(alert(Comparison::Below, -10.0).is_crossed_by(-12.0));
+    }
+
+    #[test]
+    fn keeps_alerts_per_room() {
+        let mut alerts = Alerts::new(Box::new(MemoryStorage::new()));
+        alerts.add("room-1", alert(Comparison::Above, -10.0));
+        alerts.add("room-1", alert(Comparison::Below, -30.0));
+        alerts.add("room-2", alert(Comparison::Above, 0.0));
+        assert_eq!(alerts.list("room-1").len(), 2);
+        assert_eq!(alerts.remove("room-1", 3), None);
+        assert_eq!(alerts.remove("room-1", 1), Some(alert(Comparison::Above, -10.0)));
+        assert_eq!(alerts.list("room-1"), vec![alert(Comparison::Below, -30.0)]);
+
+        alerts.update_triggered("room-2", &[(alert(Comparison::Above, 0.0), true)]);
+        assert!(alerts.list("room-2")[0].triggered);
+        alerts.remove_contexts_with_prefix("room-");
+        assert!(alerts.contexts().is_empty());
+    }
+
+    #[test]
+    fn updates_alerts_removed_during_a_check_by_identity() {
+        let mut alerts = Alerts::new(Box::new(MemoryStorage::new()));
+        alerts.add("room-1", alert(Comparison::Above, -10.0));
+        alerts.add("room-1", alert(Comparison::Below, -30.0));
+        let checked = vec![(alert(Comparison::Above, -10.0), false), (alert(Comparison::Below, -30.0), true)];
+        alerts.remove("room-1", 1);
+        alerts.update_triggered("room-1", &checked);
+        assert_eq!(alerts.list("room-1"), vec![Alert { triggered: true, ..alert(Comparison::Below, -30.0) }]);
+    }
+
+    fn snapshot(temperature: f64) -> Snapshot {
+        let mut fields = HashMap::new();
+        fields.insert("temperature".to_string(),
+                      Box::new(FieldData::Field {
+                                   ts: None,
+                                   value: FieldValue::Float(temperature),
+                               }));
+        Snapshot {
+            sdid: "d1".to_string(),
+            data: FieldData::Group(fields),
+        }
+    }
+
+    #[test]
+    fn notifies_once_per_crossing() {
+        let crossed = alert(Comparison::Above, -10.0);
+        let (messages, checked) = evaluate(&[crossed.clone()], &[snapshot(-5.0)]);
+        assert_eq!(messages.len(), 1);
+        assert_eq!(checked, vec![(crossed.clone(), true)]);
+
+        let triggered = Alert { triggered: true, ..crossed.clone() };
+        let (messages, checked) = evaluate(&[triggered.clone()], &[snapshot(-4.0)]);
+        assert!(messages.is_empty());
+        assert_eq!(checked, vec![(triggered.clone(), true)]);
+
+        let (messages, checked) = evaluate(&[triggered.clone()], &[snapshot(-15.0)]);
+        assert!(messages.is_empty());
+        assert_eq!(checked, vec![(triggered.clone(), false)]);
+
+        // without a reading of the device, nothing changes
+        let (messages, checked) = evaluate(&[triggered.clone()], &[]);
+        assert!(messages.is_empty());
+        assert_eq!(checked, vec![(triggered, true)]);
+    }
+}
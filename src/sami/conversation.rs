@@ -0,0 +1,279 @@
+use sami::Intent;
+use sami::input::NlpResponse;
+use sami::output::{MessageToUser, Payload};
+
+const ORDINALS: [&'static str; 5] = ["first", "second", "third", "fourth", "fifth"];
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Choice {
+    Device(Vec<String>),
+    Field(Vec<String>),
+}
+
+/// What is remembered of the previous messages in a room.
+#[derive(Debug, Clone, Default)]
+pub struct Conversation {
+    pub last_device: Option<Vec<String>>,
+    pub last_field: Option<String>,
+    /// A request waiting for the user to pick one of several candidates.
+    pub pending: Option<(NlpResponse, Choice)>,
+    /// The last list shown, to show its next page.
+    pub last_listing: Option<NlpResponse>,
+}
+
+fn choose<'a>(candidates: &'a [String], answer: &str) -> Option<&'a String> {
+    let answer = answer
+        .trim()
+        .trim_right_matches(|c: char| c == '?' || c == '.' || c == '!')
+        .to_lowercase();
+    let answer = answer
+        .trim_left_matches("the ")
+        .trim_right_matches(" one")
+        .trim();
+    if let Ok(index) = answer.parse::<usize>() {
+        return if index > 0 { candidates.get(index - 1) } else { None };
+    }
+    if let Some(index) = ORDINALS.iter().position(|ordinal| *ordinal == answer) {
+        return candidates.get(index);
+    }
+    if answer == "last" {
+        return candidates.last();
+    }
+    let named = candidates
+        .iter()
+        .filter(|candidate| candidate.to_lowercase().contains(&answer))
+        .collect::<Vec<&String>>();
+    match named.len() {
+        1 => Some(named[0]),
+        _ => candidates.iter().find(|candidate| candidate.to_lowercase() == answer),
+    }
+}
+
+fn device_indications(device: &str) -> Vec<String> {
+    device
+        .split_whitespace()
+        .map(|word| word.to_lowercase())
+        .collect()
+}
+
+impl Conversation {
+    /// Answers a pending question, or fills the slots missing from `nlp_response` with what was talked about before.
+    pub fn complete(&self, nlp_response: NlpResponse) -> NlpResponse {
+        if let Some((ref request, ref choice)) = self.pending {
+            if nlp_response.intent == Intent::Unknown {
+                match *choice {
+                    Choice::Device(ref candidates) => {
+                        if let Some(device) = choose(candidates, &nlp_response.query) {
+                            return NlpResponse { device: Some(device_indications(device)), ..request.clone() };
+                        }
+                    }
+                    Choice::Field(ref candidates) => {
+                        if let Some(field) = choose(candidates, &nlp_response.query) {
+                            return NlpResponse { field: Some(field.clone()), ..request.clone() };
+                        }
+                    }
+                }
+            }
+        }
+        match nlp_response.intent {
+            Intent::GetField | Intent::SetField | Intent::GetHistory | Intent::GetAggregate | Intent::Watch => {
+                NlpResponse {
+                    device: nlp_response.device.clone().or_else(|| self.last_device.clone()),
+                    field: nlp_response.field.clone().or_else(|| self.last_field.clone()),
+                    ..nlp_response
+                }
+            }
+            Intent::NextPage => {
+                match self.last_listing {
+                    Some(ref listing) => {
+                        NlpResponse {
+                            page: Some(listing.page.unwrap_or(1) + 1),
+                            ..listing.clone()
+                        }
+                    }
+                    None => nlp_response,
+                }
+            }
+            _ => nlp_response,
+        }
+    }
+
+    /// The state of the conversation once `message` has answered `nlp_response`.
+    pub fn after(&self, nlp_response: &NlpResponse, message: &MessageToUser) -> Conversation {
+        match message.payload {
+            Payload::FieldValue(ref reading) |
+            Payload::FieldSet(ref reading) => {
+                Conversation {
+                    last_device: Some(device_indications(&reading.device)),
+                    last_field: Some(reading.field.clone()),
+                    pending: None,
+                    ..self.clone()
+                }
+            }
+            Payload::History(ref history) => {
+                Conversation {
+                    last_device: Some(device_indications(&history.device)),
+                    last_field: Some(history.field.clone()),
+                    pending: None,
+                    ..self.clone()
+                }
+            }
+            Payload::Aggregate(ref aggregate) => {
+                Conversation {
+                    last_device: Some(device_indications(&aggregate.device)),
+                    last_field: Some(aggregate.field.clone()),
+                    pending: None,
+                    ..self.clone()
+                }
+            }
+            Payload::DeviceDescription(ref description) => {
+                Conversation {
+                    last_device: Some(device_indications(&description.device)),
+                    pending: None,
+                    ..self.clone()
+                }
+            }
+            Payload::Devices { .. } => {
+                Conversation {
+                    last_listing: Some(nlp_response.clone()),
+                    pending: None,
+                    ..self.clone()
+                }
+            }
+            Payload::AmbiguousDevice { ref candidates, .. } => {
+                Conversation {
+                    pending: Some((nlp_response.clone(), Choice::Device(candidates.clone()))),
+                    ..self.clone()
+                }
+            }
+            Payload::AmbiguousField {
+                ref device,
+                ref candidates,
+                ..
+            } => {
+                Conversation {
+                    last_device: Some(device_indications(device)),
+                    last_field: self.last_field.clone(),
+                    pending: Some((NlpResponse { device: Some(device_indications(device)), ..nlp_response.clone() },
+                                   Choice::Field(candidates.clone()))),
+                    ..self.clone()
+                }
+            }
+            Payload::FieldNotFound { ref device, .. } |
+            Payload::NoReading { ref device, .. } => {
+                Conversation {
+                    last_device: Some(device_indications(device)),
+                    last_field: self.last_field.clone(),
+                    pending: None,
+                    ..self.clone()
+                }
+            }
+            _ => {
+                Conversation {
+                    pending: None,
+                    ..self.clone()
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sami::Intent;
+    use sami::input::NlpResponse;
+    use sami::output::{MessageToUser, Payload, FieldReading, Status};
+
+    use super::*;
+
+    fn reading() -> MessageToUser {
+        MessageToUser {
+            intent: Intent::GetField,
+            payload: Payload::FieldValue(FieldReading {
+                                             device: "Kitchen Sensor".to_string(),
+                                             field: "temperature".to_string(),
+                                             value: "21.5".to_string(),
+                                             unit: None,
+                                             ts: None,
+                                             stale: false,
+                                         }),
+            status: Status::Info,
+        }
+    }
+
+    #[test]
+    fn fills_missing_device_from_previous_answer() {
+        let conversation = Conversation::default().after(&NlpResponse::default(), &reading());
+        let follow_up = NlpResponse {
+            intent: Intent::GetField,
+            field: Some("humidity".to_string()),
+            ..Default::default()
+        };
+        let completed = conversation.complete(follow_up);
+        assert_eq!(completed.device, Some(vec!["kitchen".to_string(), "sensor".to_string()]));
+        assert_eq!(completed.field, Some("humidity".to_string()));
+    }
+
+    #[test]
+    fn resolves_pending_disambiguation() {
+        let request = NlpResponse {
+            intent: Intent::GetField,
+            device: Some(vec!["lamp".to_string()]),
+            field: Some("state".to_string()),
+            ..Default::default()
+        };
+        let ambiguous = MessageToUser {
+            intent: Intent::GetField,
+            payload: Payload::AmbiguousDevice {
+                indication: "lamp".to_string(),
+                candidates: vec!["Desk lamp".to_string(), "Floor lamp".to_string()],
+            },
+            status: Status::ActionRequired,
+        };
+        let conversation = Conversation::default().after(&request, &ambiguous);
+        for answer in &["2", "the second one", "floor"] {
+            let completed = conversation.complete(NlpResponse {
+                                                      query: answer.to_string(),
+                                                      ..Default::default()
+                                                  });
+            assert_eq!(completed.intent, Intent::GetField);
+            assert_eq!(completed.device, Some(vec!["floor".to_string(), "lamp".to_string()]));
+            assert_eq!(completed.field, Some("state".to_string()));
+        }
+    }
+
+    #[test]
+    fn shows_next_page_of_last_listing() {
+        let listing = NlpResponse {
+            intent: Intent::ListDevices,
+            device_type: Some("thermostat".to_string()),
+            ..Default::default()
+        };
+        let listed = MessageToUser {
+            intent: Intent::ListDevices,
+            payload: Payload::Devices {
+                name: None,
+                device_type: Some("thermostat".to_string()),
+                page: 1,
+                pages: 2,
+                total: 12,
+                devices: vec![],
+            },
+            status: Status::Info,
+        };
+        let conversation = Conversation::default().after(&listing, &listed);
+        let next = conversation.complete(NlpResponse {
+                                             intent: Intent::NextPage,
+                                             ..Default::default()
+                                         });
+        assert_eq!(next.intent, Intent::ListDevices);
+        assert_eq!(next.device_type, Some("thermostat".to_string()));
+        assert_eq!(next.page, Some(2));
+    }
+
+    #[test]
+    fn ignores_unrelated_answers() {
+        assert_eq!(choose(&["Desk lamp".to_string(), "Floor lamp".to_string()], "lamp"), None);
+        assert_eq!(choose(&["Desk lamp".to_string()], "7"), None);
+    }
+}
@@ -1,9 +1,68 @@
+use futures::Future;
 
-#[derive(Default, Debug)]
+use akc;
+
+/// A time range, in milliseconds since epoch.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Period {
+    pub from: u64,
+    pub to: u64,
+}
+
+/// The statistic asked for about a field.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Aggregation {
+    Average,
+    Minimum,
+    Maximum,
+    Sum,
+    Count,
+}
+impl Aggregation {
+    pub fn of(&self, aggregate: &akc::message::Aggregate) -> f64 {
+        match *self {
+            Aggregation::Average => aggregate.mean,
+            Aggregation::Minimum => aggregate.min,
+            Aggregation::Maximum => aggregate.max,
+            Aggregation::Sum => aggregate.sum,
+            Aggregation::Count => aggregate.count as f64,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum Comparison {
+    Above,
+    Below,
+}
+
+#[derive(Default, Debug, Clone)]
 pub struct NlpResponse {
     pub intent: ::sami::Intent,
     pub device: Option<Vec<String>>,
     pub value: Option<String>,
     pub field: Option<String>,
+    pub device_type: Option<String>,
+    pub period: Option<Period>,
+    pub aggregation: Option<Aggregation>,
+    /// Breaks the aggregation down per interval.
+    pub interval: Option<akc::message::Interval>,
+    pub comparison: Option<Comparison>,
+    /// How long to do something for, in seconds.
+    pub duration: Option<u64>,
+    /// Page of a list, starting from 1.
+    pub page: Option<usize>,
     pub meta: Option<Vec<String>>,
+    /// The sentence that was understood.
+    pub query: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct NlpError {
+    pub msg: String,
+}
+
+/// Turns a user message into an `NlpResponse`.
+pub trait NlpBackend: Send + Sync {
+    fn understand(&self, query: &str) -> Box<Future<Item = NlpResponse, Error = NlpError> + Send>;
 }
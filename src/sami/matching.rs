@@ -0,0 +1,193 @@
+use std::cmp;
+
+/// Words users commonly shorten, and what they stand for in device and field names.
+const SYNONYMS: [(&'static str, &'static str); 8] = [("temp", "temperature"),
+                                                     ("hum", "humidity"),
+                                                     ("lum", "luminosity"),
+                                                     ("light", "luminosity"),
+                                                     ("bright", "brightness"),
+                                                     ("batt", "battery"),
+                                                     ("co2", "carbon"),
+                                                     ("pos", "position")];
+
+#[derive(Debug, PartialEq)]
+pub enum Matches<T> {
+    Nothing,
+    One(T),
+    Ambiguous(Vec<T>),
+}
+
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<char>>();
+    let mut previous = (0..b.len() + 1).collect::<Vec<usize>>();
+    for (i, char_a) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, char_b) in b.iter().enumerate() {
+            let substitution = previous[j] + if char_a == *char_b { 0 } else { 1 };
+            current.push(cmp::min(substitution, cmp::min(previous[j + 1] + 1, current[j] + 1)));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// Splits a name in lowercase words, on punctuation, spaces and camelCase boundaries.
+pub fn tokens(name: &str) -> Vec<String> {
+    let mut tokens = vec![];
+    let mut current = String::new();
+    let mut previous_lowercase = false;
+    for c in name.chars() {
+        if !c.is_alphanumeric() || (c.is_uppercase() && previous_lowercase) {
+            if !current.is_empty() {
+                tokens.push(current.clone());
+                current.clear();
+            }
+        }
+        if c.is_alphanumeric() {
+            current.extend(c.to_lowercase());
+        }
+        previous_lowercase = c.is_lowercase() || c.is_numeric();
+    }
+    if !current.is_empty() {
+        tokens.push(current);
+    }
+    tokens
+}
+
+fn word_score(word: &str, token: &str) -> u32 {
+    if word == token {
+        10
+    } else if word.len() >= 3 && token.starts_with(word) {
+        7
+    } else if word.len() >= 4 && edit_distance(word, token) <= 1 {
+        6
+    } else if word.len() >= 3 && token.contains(word) {
+        5
+    } else if word.len() >= 6 && edit_distance(word, token) <= 2 {
+        4
+    } else {
+        0
+    }
+}
+
+fn variants(word: &str) -> Vec<String> {
+    let mut variants = vec![word.to_string()];
+    variants.extend(SYNONYMS
+                        .iter()
+                        .filter(|&&(short, _)| short == word)
+                        .map(|&(_, long)| long.to_string()));
+    variants
+}
+
+fn indication_words(indications: &[String]) -> Vec<String> {
+    indications
+        .iter()
+        .flat_map(|indication| tokens(indication))
+        .collect()
+}
+
+/// Best score of each word against the words of `name_tokens`.
+fn word_scores(name_tokens: &[String], words: &[String]) -> Vec<u32> {
+    words
+        .iter()
+        .map(|word| {
+                 variants(word)
+                     .iter()
+                     .flat_map(|variant| name_tokens.iter().map(move |token| word_score(variant, token)))
+                     .max()
+                     .unwrap_or(0)
+             })
+        .collect()
+}
+
+/// How well `name` matches the words of `indications`, 0 meaning not at all.
+/// Every word has to match, so that a shared word alone is not enough to pick a name.
+pub fn score(name: &str, indications: &[String]) -> u32 {
+    let name_tokens = tokens(name);
+    let words = indication_words(indications);
+    let scores = word_scores(&name_tokens, &words);
+    if scores.is_empty() || scores.contains(&0) {
+        return 0;
+    }
+    let total = scores.iter().sum::<u32>();
+    if name_tokens == words { total + 20 } else { total }
+}
+
+/// How close `name` is to `indications` when some words match, to suggest names that `score` rejected.
+pub fn closeness(name: &str, indications: &[String]) -> u32 {
+    word_scores(&tokens(name), &indication_words(indications))
+        .iter()
+        .sum()
+}
+
+/// Keeps the candidates with the highest key, a tie being ambiguous.
+pub fn best<K: Ord + Copy, T>(mut scored: Vec<(K, T)>) -> Matches<T> {
+    scored.sort_by(|a, b| b.0.cmp(&a.0));
+    let top = match scored.first() {
+        Some(&(key, _)) => key,
+        None => return Matches::Nothing,
+    };
+    let mut best = scored
+        .into_iter()
+        .take_while(|&(key, _)| key == top)
+        .map(|(_, candidate)| candidate)
+        .collect::<Vec<T>>();
+    if best.len() == 1 {
+        Matches::One(best.remove(0))
+    } else {
+        Matches::Ambiguous(best)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(sentence: &str) -> Vec<String> {
+        sentence.split_whitespace().map(|word| word.to_string()).collect()
+    }
+
+    #[test]
+    fn computes_edit_distance() {
+        assert_eq!(edit_distance("kitchen", "kitchen"), 0);
+        assert_eq!(edit_distance("kitchn", "kitchen"), 1);
+        assert_eq!(edit_distance("sensor", "senosr"), 2);
+        assert_eq!(edit_distance("", "lamp"), 4);
+    }
+
+    #[test]
+    fn splits_camel_case() {
+        assert_eq!(tokens("currentTemperature"), vec!["current", "temperature"]);
+        assert_eq!(tokens("Kitchen Sensor #2"), vec!["kitchen", "sensor", "2"]);
+    }
+
+    #[test]
+    fn scores_typos_and_synonyms() {
+        assert!(score("Kitchen sensor", &words("kitchn")) > 0);
+        assert!(score("temperature", &words("temp")) > 0);
+        assert!(score("Kitchen sensor", &words("kitchen sensor")) > score("Kitchen sensor 2", &words("kitchen sensor")));
+        assert_eq!(score("Bedroom lamp", &words("kitchen")), 0);
+    }
+
+    #[test]
+    fn requires_every_word_to_match() {
+        assert_eq!(score("Kitchen sensor", &words("kitchen lamp")), 0);
+        assert_eq!(score("Bedroom lamp", &words("kitchen lamp")), 0);
+        assert!(score("Kitchen lamp", &words("kitchen lamp")) > 0);
+        assert!(score("Kitchen ceiling lamp", &words("kitchen lamp")) > 0);
+    }
+
+    #[test]
+    fn finds_close_names() {
+        assert!(closeness("Kitchen sensor", &words("kitchen lamp")) > 0);
+        assert!(closeness("Kitchen sensor", &words("kitchen sensr lamp")) > closeness("Kitchen sensor", &words("kitchen lamp")));
+        assert_eq!(closeness("Garage door", &words("kitchen lamp")), 0);
+    }
+
+    #[test]
+    fn reports_ties_as_ambiguous() {
+        assert_eq!(best(vec![(3, "a"), (5, "b"), (1, "c")]), Matches::One("b"));
+        assert_eq!(best(vec![(5, "a"), (5, "b"), (1, "c")]), Matches::Ambiguous(vec!["a", "b"]));
+        assert_eq!(best::<u32, &str>(vec![]), Matches::Nothing);
+    }
+}
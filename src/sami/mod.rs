@@ -1,13 +1,31 @@
+pub mod alerts;
+pub mod conversation;
 pub mod input;
 pub mod output;
+pub mod render;
+pub mod rules;
+pub mod watch;
 
 mod akc_request;
+mod matching;
 mod process;
 
-#[derive(Debug)]
+use akc;
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum Intent {
     SetField,
     GetField,
+    GetHistory,
+    GetAggregate,
+    CreateAlert,
+    ListAlerts,
+    DeleteAlert,
+    Watch,
+    StopWatching,
+    DescribeDevice,
+    ListDevices,
+    NextPage,
     FindDeviceType,
     Logout,
     ForcedLogout,
@@ -22,6 +40,20 @@ impl Default for Intent {
 }
 
 pub enum Error {
+    /// AKC refused the token, even once refreshed.
+    Unauthorized,
     AkcError,
     NoMatch,
+    /// Several candidates matched equally well.
+    Ambiguous(Vec<String>),
+    /// The manifest of the device type declares no action to set the field.
+    UnsupportedAction,
+}
+impl From<akc::error::AkcClientError> for Error {
+    fn from(err: akc::error::AkcClientError) -> Error {
+        match err {
+            akc::error::AkcClientError::AkcError(401, _) => Error::Unauthorized,
+            _ => Error::AkcError,
+        }
+    }
 }
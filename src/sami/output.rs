@@ -1,8 +1,20 @@
+use std::sync::Arc;
+
+use futures::Future;
+
 use oauth2;
 
 use sami::{input, process, Intent};
+use sami::alerts::Alert;
+use sami::input::{Aggregation, Period};
 
-#[derive(Debug)]
+/// Devices shown on each page of a listing.
+pub const DEVICES_PER_PAGE: usize = 10;
+
+/// Posts a message to the room of the conversation, for what comes after the answer, as watched values.
+pub type Deliver = Arc<Fn(MessageToUser) -> Box<Future<Item = (), Error = ()> + Send> + Send + Sync>;
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum Status {
     Info,
     Confirmation,
@@ -10,15 +22,185 @@ pub enum Status {
     ActionRequired,
 }
 
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldReading {
+    pub device: String,
+    /// Full path of the field, with its groups separated by dots.
+    pub field: String,
+    pub value: String,
+    pub unit: Option<String>,
+    /// Time of the reading, in milliseconds since epoch.
+    pub ts: Option<u64>,
+    pub stale: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Statistics {
+    pub min: f64,
+    pub max: f64,
+    pub average: f64,
+}
+impl Statistics {
+    pub fn of(values: &[f64]) -> Option<Statistics> {
+        if values.is_empty() {
+            return None;
+        }
+        Some(Statistics {
+                 min: values.iter().cloned().fold(values[0], f64::min),
+                 max: values.iter().cloned().fold(values[0], f64::max),
+                 average: values.iter().sum::<f64>() / values.len() as f64,
+             })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct History {
+    pub device: String,
+    pub field: String,
+    pub unit: Option<String>,
+    pub period: Period,
+    pub count: usize,
+    /// Only for numeric fields.
+    pub statistics: Option<Statistics>,
+    /// The latest values with their time, oldest first.
+    pub last_values: Vec<(u64, String)>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Aggregate {
+    pub device: String,
+    pub field: String,
+    pub unit: Option<String>,
+    pub period: Period,
+    /// Every statistic is given when nothing in particular was asked for.
+    pub aggregation: Option<Aggregation>,
+    pub count: u64,
+    pub min: f64,
+    pub max: f64,
+    pub average: f64,
+    pub sum: f64,
+    /// The asked statistic for each interval with its start, when broken down.
+    pub intervals: Vec<(u64, f64)>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Description {
+    pub device: String,
+    pub fields: Vec<FieldReading>,
+    /// Fields left out of large snapshots, and the top level groups they belong to.
+    pub omitted: usize,
+    pub omitted_groups: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeviceSummary {
+    pub name: String,
+    pub device_type: Option<String>,
+    pub connected: Option<bool>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Payload {
+    Empty,
+    User { name: String },
+    DeviceNotFound {
+        indication: String,
+        candidates: Vec<String>,
+    },
+    AmbiguousDevice {
+        indication: String,
+        candidates: Vec<String>,
+    },
+    FieldNotFound {
+        device: String,
+        field: String,
+        suggestions: Vec<String>,
+    },
+    AmbiguousField {
+        device: String,
+        field: String,
+        candidates: Vec<String>,
+    },
+    /// The field is described by the manifest but the device never sent a value for it.
+    NoReading { device: String, field: String },
+    FieldValue(FieldReading),
+    FieldSet(FieldReading),
+    History(History),
+    Aggregate(Aggregate),
+    InvalidValue {
+        device: String,
+        field: String,
+        value: String,
+        expected: String,
+    },
+    /// The device can't be told to change this field.
+    UnsupportedAction { device: String, field: String },
+    AlertCreated(Alert),
+    /// The alerts of the room, in the order used to delete them.
+    Alerts(Vec<Alert>),
+    AlertDeleted(Alert),
+    AlertNotFound { index: usize },
+    AlertTriggered { alert: Alert, value: f64 },
+    /// Alerts can only watch numeric fields.
+    NotNumeric { device: String, field: String },
+    InvalidThreshold { value: String },
+    /// The user didn't say whether the alert is for values above or below the threshold.
+    MissingComparison { threshold: f64 },
+    WatchStarted {
+        device: String,
+        field: String,
+        minutes: u64,
+    },
+    WatchStopped { device: String, field: String },
+    /// The live stream of the watched device ended with an error.
+    WatchFailed { device: String, field: String },
+    NotWatching,
+    DeviceDescription(Description),
+    Devices {
+        name: Option<String>,
+        device_type: Option<String>,
+        page: usize,
+        pages: usize,
+        total: usize,
+        devices: Vec<DeviceSummary>,
+    },
+    DeviceTypes {
+        query: String,
+        total: usize,
+        device_types: Vec<String>,
+    },
+    DeviceTypeNotFound { query: String },
+    /// AKC failed to answer, without the token being at fault.
+    RequestFailed,
+    NotUnderstood { sentence: Option<String> },
+}
+
 #[derive(Debug)]
 pub struct MessageToUser {
     pub intent: Intent,
-    pub data: Vec<String>,
+    pub payload: Payload,
     pub status: Status,
 }
 
 impl MessageToUser {
-    pub fn from(akc_token: oauth2::Token, nlp_response: input::NlpResponse) -> MessageToUser {
-        process::generate_response(akc_token, nlp_response)
+    /// `context` identifies the room the message comes from, to follow the conversation, and `deliver` posts to it.
+    pub fn from(context: &str, akc_token: oauth2::Token, nlp_response: input::NlpResponse, deliver: Deliver) -> MessageToUser {
+        process::generate_response(context, akc_token, nlp_response, deliver)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Statistics;
+
+    #[test]
+    fn computes_statistics() {
+        assert_eq!(Statistics::of(&[20.0, 22.0, 21.0]),
+                   Some(Statistics {
+                            min: 20.0,
+                            max: 22.0,
+                            average: 21.0,
+                        }));
+        assert_eq!(Statistics::of(&[]), None);
     }
 }
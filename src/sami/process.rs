@@ -1,99 +1,644 @@
+use std::cmp;
+use std::time::Duration;
+
+use serde_json;
+
 use oauth2;
+use akc;
+use time;
 
 use sami::{Intent, output, input, akc_request, Error};
+use sami::alerts::{Alert, ALERTS};
+use sami::watch::{self, Watched};
+use sami::input::Period;
+use sami::output::{Payload, FieldReading, History, Statistics, Aggregate, Description, DeviceSummary, DEVICES_PER_PAGE};
+
+use DATABASE;
+use CONVERSATIONS;
+use CONFIGURATION;
+
+const DEFAULT_WATCH_MINUTES: u64 = 10;
+const MAX_WATCH_MINUTES: u64 = 60;
+/// Fields shown when describing a device, the most recently updated being kept.
+const MAX_DESCRIBED_FIELDS: usize = 20;
+
+/// Logging out is handled here rather than by each chat adapter so they all behave the same.
+pub fn generate_response(context: &str,
+                         akc_token: oauth2::Token,
+                         nlp_response: input::NlpResponse,
+                         deliver: output::Deliver)
+                         -> output::MessageToUser {
+    let akc_token = akc_request::current_token(&akc_token);
+    let conversation = {
+        let mut lock = CONVERSATIONS.lock().unwrap();
+        lock.get(&context.to_string()).cloned().unwrap_or_default()
+    };
+    let nlp_response = conversation.complete(nlp_response);
+    let message = respond(context, &akc_token, nlp_response.clone(), deliver);
+    CONVERSATIONS
+        .lock()
+        .unwrap()
+        .insert(context.to_string(), conversation.after(&nlp_response, &message));
+    match message.intent {
+        Intent::Logout | Intent::ForcedLogout => {
+            // by room, as the token may have been refreshed while answering
+            DATABASE.lock().unwrap().remove_token(context)
+        }
+        _ => (),
+    }
+    message
+}
+
+fn is_stale(ts: Option<u64>) -> bool {
+    match ts {
+        Some(ts) => (time::get_time().sec as u64).saturating_sub(ts / 1000) > CONFIGURATION.stale_reading_after,
+        None => false,
+    }
+}
+
+fn field_not_found(akc_token: &oauth2::Token,
+                   intent: Intent,
+                   device: akc::device::Device,
+                   field_indication: String)
+                   -> output::MessageToUser {
+    let payload = if akc_request::is_known_field(akc_token, &device.dtid, &field_indication) {
+        Payload::NoReading {
+            device: device.name,
+            field: field_indication,
+        }
+    } else {
+        Payload::FieldNotFound {
+            suggestions: akc_request::suggest_fields(akc_token, &device.dtid, &field_indication),
+            device: device.name,
+            field: field_indication,
+        }
+    };
+    output::MessageToUser {
+        intent: intent,
+        payload: payload,
+        status: output::Status::Error,
+    }
+}
+
+fn forced_logout() -> output::MessageToUser {
+    output::MessageToUser {
+        intent: Intent::ForcedLogout,
+        payload: Payload::Empty,
+        status: output::Status::Error,
+    }
+}
+
+/// Logs out only when the token is at fault.
+fn failed(intent: Intent, error: Error) -> output::MessageToUser {
+    match error {
+        Error::Unauthorized => forced_logout(),
+        _ => {
+            output::MessageToUser {
+                intent: intent,
+                payload: Payload::RequestFailed,
+                status: output::Status::Error,
+            }
+        }
+    }
+}
+
+/// Finds the device and the field the user is talking about, answering with what went wrong if they can't be found.
+fn with_device_and_field<F>(akc_token: &oauth2::Token, intent: Intent, nlp_response: &input::NlpResponse, found: F) -> output::MessageToUser
+    where F: FnOnce(Intent, akc::device::Device, akc_request::FieldValueAndPath) -> output::MessageToUser
+{
+    let field_indication = nlp_response
+        .field
+        .clone()
+        .unwrap_or_else(|| "no field".to_string());
+    with_device(akc_token,
+                intent,
+                nlp_response,
+                |intent, device| match akc_request::find_field_value_with(akc_token, &device.id, &field_indication) {
+                    Ok(field_value_and_path) => found(intent, device, field_value_and_path),
+                    Err(Error::NoMatch) => field_not_found(akc_token, intent, device, field_indication),
+                    Err(Error::Ambiguous(candidates)) => {
+                        output::MessageToUser {
+                            intent: intent,
+                            payload: Payload::AmbiguousField {
+                                device: device.name,
+                                field: field_indication,
+                                candidates: candidates,
+                            },
+                            status: output::Status::ActionRequired,
+                        }
+                    }
+                    Err(err) => failed(intent, err),
+                })
+}
+
+/// Finds the device the user is talking about, answering with what went wrong if it can't be found.
+fn with_device<F>(akc_token: &oauth2::Token, intent: Intent, nlp_response: &input::NlpResponse, found: F) -> output::MessageToUser
+    where F: FnOnce(Intent, akc::device::Device) -> output::MessageToUser
+{
+    let device_indications = nlp_response
+        .device
+        .clone()
+        .unwrap_or_else(|| vec!["no device specified".to_string()]);
+    match akc_request::find_device_with(akc_token, &device_indications) {
+        Ok(device) => found(intent, device),
+        Err(Error::NoMatch) => {
+            output::MessageToUser {
+                intent: intent,
+                payload: Payload::DeviceNotFound {
+                    indication: device_indications.join(" "),
+                    candidates: akc_request::find_closest_devices(akc_token, &device_indications).unwrap_or_default(),
+                },
+                status: output::Status::Error,
+            }
+        }
+        Err(Error::Ambiguous(candidates)) => {
+            output::MessageToUser {
+                intent: intent,
+                payload: Payload::AmbiguousDevice {
+                    indication: device_indications.join(" "),
+                    candidates: candidates,
+                },
+                status: output::Status::ActionRequired,
+            }
+        }
+        Err(err) => failed(intent, err),
+    }
+}
+
+/// Keeps the most recently updated fields of large snapshots, listing the groups of the others.
+fn describe(akc_token: &oauth2::Token, device: akc::device::Device, fields: Vec<akc_request::FieldValueAndPath>) -> Payload {
+    let mut by_age = fields;
+    by_age.sort_by(|a, b| b.ts.cmp(&a.ts));
+    let omitted = by_age.split_off(cmp::min(MAX_DESCRIBED_FIELDS, by_age.len()));
+    let mut omitted_groups = omitted
+        .iter()
+        .map(|field| field.path.get(0).unwrap_or(&field.name).clone())
+        .collect::<Vec<String>>();
+    omitted_groups.sort();
+    omitted_groups.dedup();
+    let mut shown = by_age;
+    shown.sort_by_key(|field| field.full_name());
+    Payload::DeviceDescription(Description {
+                                   fields: shown
+                                       .iter()
+                                       .map(|field| {
+                                                FieldReading {
+                                                    device: device.name.clone(),
+                                                    field: field.full_name(),
+                                                    value: field.value.to_string(),
+                                                    unit: akc_request::find_unit(akc_token, &device.dtid, field),
+                                                    ts: field.ts,
+                                                    stale: is_stale(field.ts),
+                                                }
+                                            })
+                                       .collect(),
+                                   device: device.name,
+                                   omitted: omitted.len(),
+                                   omitted_groups: omitted_groups,
+                               })
+}
 
-pub fn generate_response(akc_token: oauth2::Token, nlp_response: input::NlpResponse) -> output::MessageToUser {
+fn summarize(device: akc::device::Device,
+             field: String,
+             unit: Option<String>,
+             period: Period,
+             values: Vec<(u64, serde_json::Value)>)
+             -> History {
+    let numbers = values
+        .iter()
+        .filter_map(|&(_, ref value)| value.as_f64())
+        .collect::<Vec<f64>>();
+    History {
+        device: device.name,
+        field: field,
+        unit: unit,
+        period: period,
+        count: values.len(),
+        statistics: if numbers.len() == values.len() {
+            Statistics::of(&numbers)
+        } else {
+            None
+        },
+        last_values: values
+            .iter()
+            .skip(values.len().saturating_sub(5))
+            .map(|&(ts, ref value)| (ts, value.to_string()))
+            .collect(),
+    }
+}
+
+fn last_day() -> Period {
+    let now = time::get_time().sec as u64 * 1000;
+    Period {
+        from: now - 24 * 60 * 60 * 1000,
+        to: now,
+    }
+}
+
+fn respond(context: &str, akc_token: &oauth2::Token, nlp_response: input::NlpResponse, deliver: output::Deliver) -> output::MessageToUser {
     info!("{:?}", nlp_response);
     match nlp_response.intent {
 
         intent @ Intent::GetSelf => {
-            match akc_request::find_user(&akc_token) {
+            match akc_request::find_user(akc_token) {
                 Ok(user) => {
                     output::MessageToUser {
                         intent: intent,
-                        data: vec![user.full_name],
+                        payload: Payload::User { name: user.full_name },
                         status: output::Status::Info,
                     }
                 }
-                Err(_) => {
-                    output::MessageToUser {
-                        intent: Intent::ForcedLogout,
-                        data: vec![akc_token.access_token().to_string()],
-                        status: output::Status::Error,
-                    }
-                }
+                Err(err) => failed(intent, err),
             }
         }
 
         intent @ Intent::Logout => {
             output::MessageToUser {
                 intent: intent,
-                data: vec![akc_token.access_token().to_string()],
+                payload: Payload::Empty,
                 status: output::Status::Confirmation,
             }
         }
 
         intent @ Intent::GetField => {
-            let device_indications = nlp_response
-                .device
-                .unwrap_or_else(|| vec!["no device specified".to_string()]);
-            let field_indication = nlp_response.field.unwrap_or_else(|| "no field".to_string());
-            match akc_request::find_device_with(&akc_token, &device_indications) {
-                Ok(device) => {
-                    match akc_request::find_field_value_with(&akc_token, &device.id, &field_indication) {
-                        Ok(field_value_and_path) => {
-                            output::MessageToUser {
-                                intent: intent,
-                                data: vec![device.name,
-                                           format!("{}{}{}",
-                                                   field_value_and_path.path.join("."),
-                                                   if field_value_and_path.path.is_empty() {
-                                                       ""
-                                                   } else {
-                                                       "."
-                                                   },
-                                                   field_value_and_path.name),
-                                           field_value_and_path.value.to_string()],
-                                status: output::Status::Info,
+            with_device_and_field(akc_token, intent, &nlp_response, |intent, device, field_value_and_path| {
+                output::MessageToUser {
+                    intent: intent,
+                    payload: Payload::FieldValue(FieldReading {
+                                                     unit: akc_request::find_unit(akc_token, &device.dtid, &field_value_and_path),
+                                                     device: device.name,
+                                                     field: field_value_and_path.full_name(),
+                                                     value: field_value_and_path.value.to_string(),
+                                                     ts: field_value_and_path.ts,
+                                                     stale: is_stale(field_value_and_path.ts),
+                                                 }),
+                    status: output::Status::Info,
+                }
+            })
+        }
+
+        intent @ Intent::SetField => {
+            let value_indication = nlp_response
+                .value
+                .clone()
+                .unwrap_or_else(|| "no value".to_string());
+            with_device_and_field(akc_token, intent, &nlp_response, |intent, device, field_value_and_path| {
+                match field_value_and_path.value.parse_like(&value_indication) {
+                    Some(value) => {
+                        match akc_request::set_field_value(akc_token, &device, &field_value_and_path, value.clone()) {
+                            Ok(()) => {
+                                output::MessageToUser {
+                                    intent: intent,
+                                    payload: Payload::FieldSet(FieldReading {
+                                                                   unit: akc_request::find_unit(akc_token,
+                                                                                                &device.dtid,
+                                                                                                &field_value_and_path),
+                                                                   device: device.name,
+                                                                   field: field_value_and_path.full_name(),
+                                                                   value: value.to_string(),
+                                                                   ts: None,
+                                                                   stale: false,
+                                                               }),
+                                    status: output::Status::Confirmation,
+                                }
                             }
-                        }
-                        Err(Error::NoMatch) => {
-                            output::MessageToUser {
-                                intent: intent,
-                                data: vec![device.name, field_indication],
-                                status: output::Status::Error,
+                            Err(Error::UnsupportedAction) => {
+                                output::MessageToUser {
+                                    intent: intent,
+                                    payload: Payload::UnsupportedAction {
+                                        device: device.name,
+                                        field: field_value_and_path.full_name(),
+                                    },
+                                    status: output::Status::Error,
+                                }
                             }
+                            Err(err) => failed(intent, err),
                         }
-                        Err(Error::AkcError) => {
-                            output::MessageToUser {
-                                intent: Intent::ForcedLogout,
-                                data: vec![akc_token.access_token().to_string()],
-                                status: output::Status::Error,
-                            }
+                    }
+                    None => {
+                        output::MessageToUser {
+                            intent: intent,
+                            payload: Payload::InvalidValue {
+                                device: device.name,
+                                field: field_value_and_path.full_name(),
+                                value: value_indication,
+                                expected: field_value_and_path.value.type_name().to_string(),
+                            },
+                            status: output::Status::Error,
                         }
                     }
                 }
-                Err(Error::NoMatch) => {
+            })
+        }
+
+        intent @ Intent::GetHistory => {
+            let period = nlp_response.period.unwrap_or_else(last_day);
+            with_device_and_field(akc_token, intent, &nlp_response, |intent, device, field_value_and_path| {
+                match akc_request::find_history(akc_token, &device.id, &field_value_and_path, period) {
+                    Ok(values) => {
+                        let unit = akc_request::find_unit(akc_token, &device.dtid, &field_value_and_path);
+                        output::MessageToUser {
+                            intent: intent,
+                            payload: Payload::History(summarize(device, field_value_and_path.full_name(), unit, period, values)),
+                            status: output::Status::Info,
+                        }
+                    }
+                    Err(err) => failed(intent, err),
+                }
+            })
+        }
+
+        intent @ Intent::GetAggregate => {
+            let period = nlp_response.period.unwrap_or_else(last_day);
+            let aggregation = nlp_response.aggregation;
+            let interval = nlp_response.interval;
+            with_device_and_field(akc_token, intent, &nlp_response, |intent, device, field_value_and_path| {
+                if field_value_and_path.value.as_f64().is_none() {
+                    return output::MessageToUser {
+                               intent: intent,
+                               payload: Payload::NotNumeric {
+                                   device: device.name,
+                                   field: field_value_and_path.full_name(),
+                               },
+                               status: output::Status::Error,
+                           };
+                }
+                let aggregate = akc_request::find_aggregate(akc_token, &device.id, &field_value_and_path, period);
+                // the statistics of the whole period are still worth showing without the intervals
+                let intervals = match interval {
+                    Some(interval) => {
+                        akc_request::find_histogram(akc_token, &device.id, &field_value_and_path, period, interval).unwrap_or_default()
+                    }
+                    None => vec![],
+                };
+                match aggregate {
+                    Ok(aggregate) => {
+                        let aggregate = aggregate.unwrap_or_default();
+                        output::MessageToUser {
+                            intent: intent,
+                            payload: Payload::Aggregate(Aggregate {
+                                                            unit: akc_request::find_unit(akc_token, &device.dtid, &field_value_and_path),
+                                                            device: device.name,
+                                                            field: field_value_and_path.full_name(),
+                                                            period: period,
+                                                            aggregation: aggregation,
+                                                            count: aggregate.count,
+                                                            min: aggregate.min,
+                                                            max: aggregate.max,
+                                                            average: aggregate.mean,
+                                                            sum: aggregate.sum,
+                                                            intervals: intervals
+                                                                .iter()
+                                                                .map(|bucket| {
+                                                                         (bucket.ts.unwrap_or(period.from),
+                                                                          aggregation
+                                                                              .unwrap_or(input::Aggregation::Average)
+                                                                              .of(bucket))
+                                                                     })
+                                                                .collect(),
+                                                        }),
+                            status: output::Status::Info,
+                        }
+                    }
+                    Err(err) => failed(intent, err),
+                }
+            })
+        }
+
+        intent @ Intent::CreateAlert => {
+            let value_indication = nlp_response
+                .value
+                .clone()
+                .unwrap_or_else(|| "no value".to_string());
+            let threshold = match value_indication.trim().parse::<f64>() {
+                Ok(threshold) => threshold,
+                Err(_) => {
+                    return output::MessageToUser {
+                               intent: intent,
+                               payload: Payload::InvalidThreshold { value: value_indication },
+                               status: output::Status::Error,
+                           }
+                }
+            };
+            let comparison = match nlp_response.comparison {
+                Some(comparison) => comparison,
+                None => {
+                    return output::MessageToUser {
+                               intent: intent,
+                               payload: Payload::MissingComparison { threshold: threshold },
+                               status: output::Status::ActionRequired,
+                           }
+                }
+            };
+            with_device_and_field(akc_token, intent, &nlp_response, |intent, device, field_value_and_path| {
+                if field_value_and_path.value.as_f64().is_none() {
+                    return output::MessageToUser {
+                               intent: intent,
+                               payload: Payload::NotNumeric {
+                                   device: device.name,
+                                   field: field_value_and_path.full_name(),
+                               },
+                               status: output::Status::Error,
+                           };
+                }
+                let mut path = field_value_and_path.path.clone();
+                path.push(field_value_and_path.name.clone());
+                let alert = Alert {
+                    unit: akc_request::find_unit(akc_token, &device.dtid, &field_value_and_path),
+                    device_id: device.id,
+                    device: device.name,
+                    path: path,
+                    comparison: comparison,
+                    threshold: threshold,
+                    triggered: false,
+                };
+                ALERTS.lock().unwrap().add(context, alert.clone());
+                output::MessageToUser {
+                    intent: intent,
+                    payload: Payload::AlertCreated(alert),
+                    status: output::Status::Confirmation,
+                }
+            })
+        }
+
+        intent @ Intent::ListAlerts => {
+            output::MessageToUser {
+                intent: intent,
+                payload: Payload::Alerts(ALERTS.lock().unwrap().list(context)),
+                status: output::Status::Info,
+            }
+        }
+
+        intent @ Intent::DeleteAlert => {
+            let index = nlp_response
+                .value
+                .and_then(|value| value.trim().parse::<usize>().ok())
+                .unwrap_or(0);
+            let removed = ALERTS.lock().unwrap().remove(context, index);
+            match removed {
+                Some(alert) => {
+                    output::MessageToUser {
+                        intent: intent,
+                        payload: Payload::AlertDeleted(alert),
+                        status: output::Status::Confirmation,
+                    }
+                }
+                None => {
                     output::MessageToUser {
                         intent: intent,
-                        data: vec![device_indications.join(" ")],
+                        payload: Payload::AlertNotFound { index: index },
                         status: output::Status::Error,
                     }
                 }
-                Err(Error::AkcError) => {
+            }
+        }
+
+        intent @ Intent::Watch => {
+            let minutes = nlp_response
+                .duration
+                .map(|duration| cmp::max(1, duration / 60))
+                .unwrap_or(DEFAULT_WATCH_MINUTES);
+            let minutes = cmp::min(minutes, MAX_WATCH_MINUTES);
+            with_device_and_field(akc_token, intent, &nlp_response, |intent, device, field_value_and_path| {
+                let mut path = field_value_and_path.path.clone();
+                path.push(field_value_and_path.name.clone());
+                let watched = Watched {
+                    unit: akc_request::find_unit(akc_token, &device.dtid, &field_value_and_path),
+                    device_id: device.id,
+                    device: device.name,
+                    path: path,
+                };
+                watch::start(context,
+                             akc_token.clone(),
+                             watched.clone(),
+                             Duration::from_secs(minutes * 60),
+                             deliver);
+                output::MessageToUser {
+                    intent: intent,
+                    payload: Payload::WatchStarted {
+                        device: watched.device,
+                        field: watched.path.join("."),
+                        minutes: minutes,
+                    },
+                    status: output::Status::Confirmation,
+                }
+            })
+        }
+
+        intent @ Intent::StopWatching => {
+            match watch::stop(context) {
+                Some(watched) => watch::stopped(watched),
+                None => {
                     output::MessageToUser {
-                        intent: Intent::ForcedLogout,
-                        data: vec![akc_token.access_token().to_string()],
+                        intent: intent,
+                        payload: Payload::NotWatching,
                         status: output::Status::Error,
                     }
                 }
             }
         }
 
+        intent @ Intent::FindDeviceType => {
+            let device_type_indication = nlp_response.device_type.unwrap_or_else(|| "no device type".to_string());
+            match akc_request::find_device_types_with(akc_token, &device_type_indication) {
+                Ok(device_types) => {
+                    output::MessageToUser {
+                        intent: intent,
+                        payload: Payload::DeviceTypes {
+                            query: device_type_indication,
+                            total: device_types.len(),
+                            device_types: device_types
+                                .iter()
+                                .take(10)
+                                .map(|device_type| format!("{} ({})", device_type.name, device_type.unique_name))
+                                .collect(),
+                        },
+                        status: output::Status::Info,
+                    }
+                }
+                Err(Error::NoMatch) => {
+                    output::MessageToUser {
+                        intent: intent,
+                        payload: Payload::DeviceTypeNotFound { query: device_type_indication },
+                        status: output::Status::Error,
+                    }
+                }
+                Err(err) => failed(intent, err),
+            }
+        }
+
+        intent @ Intent::DescribeDevice => {
+            with_device(akc_token,
+                        intent,
+                        &nlp_response,
+                        |intent, device| match akc_request::find_field_values(akc_token, &device.id) {
+                            Ok(fields) => {
+                                output::MessageToUser {
+                                    intent: intent,
+                                    payload: describe(akc_token, device, fields),
+                                    status: output::Status::Info,
+                                }
+                            }
+                            Err(Error::NoMatch) => {
+                                output::MessageToUser {
+                                    intent: intent,
+                                    payload: Payload::DeviceDescription(Description {
+                                                                           device: device.name,
+                                                                           fields: vec![],
+                                                                           omitted: 0,
+                                                                           omitted_groups: vec![],
+                                                                       }),
+                                    status: output::Status::Info,
+                                }
+                            }
+                            Err(err) => failed(intent, err),
+                        })
+        }
+
+        intent @ Intent::ListDevices => {
+            let name = nlp_response.device.as_ref().map(|device| &device[..]);
+            let device_type = nlp_response.device_type.as_ref().map(|device_type| &device_type[..]);
+            let devices = match akc_request::find_devices_matching(akc_token, name, device_type) {
+                Ok(devices) => devices,
+                Err(err) => return failed(intent, err),
+            };
+            let pages = cmp::max(1, (devices.len() + DEVICES_PER_PAGE - 1) / DEVICES_PER_PAGE);
+            let page = cmp::min(cmp::max(1, nlp_response.page.unwrap_or(1)), pages);
+            output::MessageToUser {
+                intent: intent,
+                payload: Payload::Devices {
+                    name: nlp_response.device.map(|device| device.join(" ")),
+                    device_type: nlp_response.device_type,
+                    page: page,
+                    pages: pages,
+                    total: devices.len(),
+                    devices: devices
+                        .into_iter()
+                        .skip((page - 1) * DEVICES_PER_PAGE)
+                        .take(DEVICES_PER_PAGE)
+                        .map(|device| {
+                                 DeviceSummary {
+                                     device_type: akc_request::find_device_type(akc_token, &device.dtid)
+                                         .ok()
+                                         .map(|device_type| device_type.name),
+                                     name: device.name,
+                                     connected: device.connected,
+                                 }
+                             })
+                        .collect(),
+                },
+                status: output::Status::Info,
+            }
+        }
+
         intent => {
             output::MessageToUser {
                 intent,
-                data: nlp_response.meta.unwrap_or_else(|| vec![]),
+                payload: Payload::NotUnderstood {
+                    sentence: nlp_response
+                        .meta
+                        .and_then(|meta| meta.into_iter().next()),
+                },
                 status: output::Status::Error,
             }
         }
@@ -0,0 +1,792 @@
+use time;
+
+use sami::Intent;
+use sami::alerts::Alert;
+use sami::input::{Aggregation, Comparison};
+use sami::output::{MessageToUser, Payload, FieldReading, History, Aggregate, DEVICES_PER_PAGE};
+
+/// A date in milliseconds since epoch, as "2017-07-14 02:40 UTC".
+pub fn date(ms: u64) -> String {
+    time::at_utc(time::Timespec::new((ms / 1000) as i64, 0))
+        .strftime("%Y-%m-%d %H:%M UTC")
+        .map(|date| date.to_string())
+        .unwrap_or_else(|_| ms.to_string())
+}
+
+/// A number without useless decimals.
+pub fn number(value: f64) -> String {
+    let formatted = format!("{:.2}", value);
+    formatted
+        .trim_right_matches('0')
+        .trim_right_matches('.')
+        .to_string()
+}
+
+/// Age of a reading, as "3 minutes ago".
+pub fn relative_age(seconds: u64) -> String {
+    let (count, unit) = match seconds {
+        0...9 => return "just now".to_string(),
+        10...59 => (seconds, "second"),
+        60...3599 => (seconds / 60, "minute"),
+        3600...86399 => (seconds / 3600, "hour"),
+        _ => (seconds / 86400, "day"),
+    };
+    format!("{} {}{} ago", count, unit, if count > 1 { "s" } else { "" })
+}
+
+/// `text` cut to `max` characters.
+pub fn shorten(text: &str, max: usize) -> String {
+    if text.chars().count() <= max {
+        return text.to_string();
+    }
+    let mut shortened = text.chars().take(max - 1).collect::<String>();
+    shortened.push('…');
+    shortened
+}
+
+/// Longer values are cut when showing a whole device.
+const MAX_VALUE_LENGTH: usize = 40;
+
+/// Formatting of a chat platform. The wording of the answers is shared by every platform through `render`.
+pub trait Renderer {
+    fn escape(&self, text: &str) -> String;
+    fn strong(&self, text: &str) -> String;
+    fn code(&self, text: &str) -> String;
+    fn line_break(&self) -> &'static str;
+
+    fn value_with_unit(&self, reading: &FieldReading) -> String {
+        match reading.unit {
+            Some(ref unit) => self.strong(&self.escape(&format!("{} {}", reading.value, unit))),
+            None => self.strong(&self.escape(&reading.value)),
+        }
+    }
+
+    fn history(&self, history: &History) -> String {
+        let with_unit = |value: String| match history.unit {
+            Some(ref unit) => format!("{} {}", value, unit),
+            None => value,
+        };
+        let subject = format!("{}'s {} between {} and {}",
+                              self.escape(&history.device),
+                              self.code(&self.escape(&history.field)),
+                              date(history.period.from),
+                              date(history.period.to));
+        if history.count == 0 {
+            return format!("No value for {}.", subject);
+        }
+        match history.statistics {
+            Some(ref statistics) => {
+                format!("{}: min {}, max {}, average {} over {} values.",
+                        subject,
+                        self.strong(&self.escape(&with_unit(number(statistics.min)))),
+                        self.strong(&self.escape(&with_unit(number(statistics.max)))),
+                        self.strong(&self.escape(&with_unit(number(statistics.average)))),
+                        history.count)
+            }
+            None => {
+                format!("{}: {} values, the latest being:{}{}",
+                        subject,
+                        history.count,
+                        self.line_break(),
+                        self.list(&history
+                                       .last_values
+                                       .iter()
+                                       .map(|&(ts, ref value)| format!("{} at {}", with_unit(value.clone()), date(ts)))
+                                       .collect::<Vec<String>>()))
+            }
+        }
+    }
+
+    fn aggregate(&self, aggregate: &Aggregate) -> String {
+        let with_unit = |value: f64| match aggregate.unit {
+            Some(ref unit) => format!("{} {}", number(value), unit),
+            None => number(value),
+        };
+        let subject = format!("{}'s {} between {} and {}",
+                              self.escape(&aggregate.device),
+                              self.code(&self.escape(&aggregate.field)),
+                              date(aggregate.period.from),
+                              date(aggregate.period.to));
+        if aggregate.count == 0 {
+            return format!("No value for {}.", subject);
+        }
+        let summary = match aggregate.aggregation {
+            Some(Aggregation::Count) => format!("{} values for {}.", self.strong(&aggregate.count.to_string()), subject),
+            Some(aggregation) => {
+                let (name, value) = match aggregation {
+                    Aggregation::Minimum => ("minimum", aggregate.min),
+                    Aggregation::Maximum => ("maximum", aggregate.max),
+                    Aggregation::Sum => ("total", aggregate.sum),
+                    _ => ("average", aggregate.average),
+                };
+                format!("The {} of {} is {} over {} values.",
+                        name,
+                        subject,
+                        self.strong(&self.escape(&with_unit(value))),
+                        aggregate.count)
+            }
+            None => {
+                format!("{}: min {}, max {}, average {}, total {} over {} values.",
+                        subject,
+                        self.strong(&self.escape(&with_unit(aggregate.min))),
+                        self.strong(&self.escape(&with_unit(aggregate.max))),
+                        self.strong(&self.escape(&with_unit(aggregate.average))),
+                        self.strong(&self.escape(&with_unit(aggregate.sum))),
+                        aggregate.count)
+            }
+        };
+        if aggregate.intervals.is_empty() {
+            return summary;
+        }
+        let intervals = aggregate
+            .intervals
+            .iter()
+            .map(|&(ts, value)| match aggregate.aggregation {
+                     Some(Aggregation::Count) => format!("{}: {}", date(ts), number(value)),
+                     _ => format!("{}: {}", date(ts), with_unit(value)),
+                 })
+            .collect::<Vec<String>>();
+        format!("{}{}{}", summary, self.line_break(), self.list(&intervals))
+    }
+
+    /// The condition of an alert, as "above -10 °C".
+    fn condition(&self, alert: &Alert) -> String {
+        let threshold = match alert.unit {
+            Some(ref unit) => format!("{} {}", number(alert.threshold), unit),
+            None => number(alert.threshold),
+        };
+        match alert.comparison {
+            Comparison::Above => format!("above {}", threshold),
+            Comparison::Below => format!("below {}", threshold),
+        }
+    }
+
+    fn list(&self, items: &[String]) -> String {
+        self.list_from(items, 1)
+    }
+
+    /// Rows of already formatted cells.
+    fn table(&self, rows: &[Vec<String>]) -> String {
+        rows.iter()
+            .map(|row| row.join(" | "))
+            .collect::<Vec<String>>()
+            .join(self.line_break())
+    }
+
+    /// A numbered list whose first item is numbered `first`.
+    fn list_from(&self, items: &[String], first: usize) -> String {
+        items
+            .iter()
+            .enumerate()
+            .map(|(i, item)| format!("{}. {}", i + first, self.escape(item)))
+            .collect::<Vec<String>>()
+            .join(self.line_break())
+    }
+
+    fn render(&self, message: &MessageToUser) -> String {
+        self.render_at(message, time::get_time().sec as u64)
+    }
+
+    /// Renders `message` as seen at `now`, in seconds since epoch.
+    fn render_at(&self, message: &MessageToUser, now: u64) -> String {
+        match message.payload {
+            Payload::Empty => {
+                match message.intent {
+                    Intent::Logout => "You are now logged out.".to_string(),
+                    Intent::ForcedLogout => "Error communicating with ARTIK Cloud. You have been logged out.".to_string(),
+                    _ => "uuuh ?".to_string(),
+                }
+            }
+            Payload::User { ref name } => format!("You are connected as {}.", self.strong(&self.escape(name))),
+            Payload::DeviceNotFound { ref indication, ref candidates } => {
+                if candidates.is_empty() {
+                    format!("No device found for '{}'.", self.escape(indication))
+                } else {
+                    format!("No device found for '{}'. Did you mean {}?",
+                            self.escape(indication),
+                            candidates
+                                .iter()
+                                .map(|candidate| self.strong(&self.escape(candidate)))
+                                .collect::<Vec<String>>()
+                                .join(", "))
+                }
+            }
+            Payload::AmbiguousDevice { ref indication, ref candidates } => {
+                format!("Several devices match '{}', which one do you mean?{}{}",
+                        self.escape(indication),
+                        self.line_break(),
+                        self.list(candidates))
+            }
+            Payload::AmbiguousField {
+                ref device,
+                ref field,
+                ref candidates,
+            } => {
+                format!("Several fields of {} match '{}', which one do you mean?{}{}",
+                        self.escape(device),
+                        self.escape(field),
+                        self.line_break(),
+                        self.list(candidates))
+            }
+            Payload::FieldNotFound {
+                ref device,
+                ref field,
+                ref suggestions,
+            } => {
+                if suggestions.is_empty() {
+                    format!("No field '{}' found for device '{}'.",
+                            self.escape(field),
+                            self.escape(device))
+                } else {
+                    format!("No field '{}' found for device '{}'. Available fields: {}.",
+                            self.escape(field),
+                            self.escape(device),
+                            suggestions
+                                .iter()
+                                .map(|suggestion| self.code(&self.escape(suggestion)))
+                                .collect::<Vec<String>>()
+                                .join(", "))
+                }
+            }
+            Payload::NoReading { ref device, ref field } => {
+                format!("{} has not sent any value for {} yet.",
+                        self.escape(device),
+                        self.code(&self.escape(field)))
+            }
+            Payload::FieldValue(ref reading) => {
+                let mut answer = format!("{}'s {} is {}",
+                                         self.escape(&reading.device),
+                                         self.code(&self.escape(&reading.field)),
+                                         self.value_with_unit(reading));
+                if let Some(ts) = reading.ts {
+                    answer.push_str(&format!(" ({})", relative_age(now.saturating_sub(ts / 1000))));
+                }
+                answer.push('.');
+                if reading.stale {
+                    answer.push_str(" This reading may be outdated.");
+                }
+                answer
+            }
+            Payload::FieldSet(ref reading) => {
+                format!("{}'s {} has been set to {}.",
+                        self.escape(&reading.device),
+                        self.code(&self.escape(&reading.field)),
+                        self.value_with_unit(reading))
+            }
+            Payload::History(ref history) => self.history(history),
+            Payload::Aggregate(ref aggregate) => self.aggregate(aggregate),
+            Payload::InvalidValue {
+                ref device,
+                ref field,
+                ref value,
+                ref expected,
+            } => {
+                format!("Can't set {}'s {} to '{}': expected a {} value.",
+                        self.escape(device),
+                        self.code(&self.escape(field)),
+                        self.escape(value),
+                        expected)
+            }
+            Payload::UnsupportedAction { ref device, ref field } => {
+                format!("{} has no action to set its {}.",
+                        self.escape(device),
+                        self.code(&self.escape(field)))
+            }
+            Payload::AlertCreated(ref alert) => {
+                format!("I will notify this room when {}'s {} goes {}.",
+                        self.escape(&alert.device),
+                        self.code(&self.escape(&alert.field())),
+                        self.strong(&self.escape(&self.condition(alert))))
+            }
+            Payload::Alerts(ref alerts) => {
+                if alerts.is_empty() {
+                    "There is no alert in this room.".to_string()
+                } else {
+                    format!("Alerts of this room:{}{}",
+                            self.line_break(),
+                            self.list(&alerts
+                                           .iter()
+                                           .map(|alert| format!("{}'s {} {}", alert.device, alert.field(), self.condition(alert)))
+                                           .collect::<Vec<String>>()))
+                }
+            }
+            Payload::AlertDeleted(ref alert) => {
+                format!("The alert on {}'s {} going {} has been deleted.",
+                        self.escape(&alert.device),
+                        self.code(&self.escape(&alert.field())),
+                        self.escape(&self.condition(alert)))
+            }
+            Payload::AlertNotFound { index } => format!("There is no alert {} in this room.", index),
+            Payload::AlertTriggered { ref alert, value } => {
+                let value = match alert.unit {
+                    Some(ref unit) => format!("{} {}", number(value), unit),
+                    None => number(value),
+                };
+                format!("Alert: {}'s {} is {}, {}.",
+                        self.escape(&alert.device),
+                        self.code(&self.escape(&alert.field())),
+                        self.strong(&self.escape(&value)),
+                        self.escape(&self.condition(alert)))
+            }
+            Payload::NotNumeric { ref device, ref field } => {
+                format!("{} need a numeric field, but {}'s {} is not a number.",
+                        match message.intent {
+                            Intent::CreateAlert => "Alerts",
+                            _ => "Statistics",
+                        },
+                        self.escape(device),
+                        self.code(&self.escape(field)))
+            }
+            Payload::MissingComparison { threshold } => {
+                format!("Should the alert be for values above or below {}? Please ask again with 'above' or 'below'.",
+                        threshold)
+            }
+            Payload::InvalidThreshold { ref value } => format!("'{}' is not a valid threshold, expected a number.", self.escape(value)),
+            Payload::WatchStarted {
+                ref device,
+                ref field,
+                minutes,
+            } => {
+                format!("Watching {}'s {} for {} minute{}, say 'stop watching' to stop.",
+                        self.escape(device),
+                        self.code(&self.escape(field)),
+                        minutes,
+                        if minutes > 1 { "s" } else { "" })
+            }
+            Payload::WatchStopped { ref device, ref field } => {
+                format!("Stopped watching {}'s {}.",
+                        self.escape(device),
+                        self.code(&self.escape(field)))
+            }
+            Payload::WatchFailed { ref device, ref field } => {
+                format!("Stopped watching {}'s {}, ARTIK Cloud refused or dropped the subscription.",
+                        self.escape(device),
+                        self.code(&self.escape(field)))
+            }
+            Payload::NotWatching => "Nothing is being watched in this room.".to_string(),
+            Payload::DeviceDescription(ref description) => {
+                if description.fields.is_empty() {
+                    return format!("{} has not sent any value yet.", self.escape(&description.device));
+                }
+                let rows = description
+                    .fields
+                    .iter()
+                    .map(|reading| {
+                        let value = FieldReading {
+                            value: shorten(&reading.value, MAX_VALUE_LENGTH),
+                            ..reading.clone()
+                        };
+                        let age = match reading.ts {
+                            Some(ts) if reading.stale => format!("{}, outdated", relative_age(now.saturating_sub(ts / 1000))),
+                            Some(ts) => relative_age(now.saturating_sub(ts / 1000)),
+                            None => "".to_string(),
+                        };
+                        vec![self.code(&self.escape(&reading.field)), self.value_with_unit(&value), age]
+                    })
+                    .collect::<Vec<Vec<String>>>();
+                let mut answer = format!("{}'s latest values:{}{}",
+                                         self.escape(&description.device),
+                                         self.line_break(),
+                                         self.table(&rows));
+                if description.omitted > 0 {
+                    answer.push_str(&format!("{}... and {} more field{} in {}, ask for one by name.",
+                                             self.line_break(),
+                                             description.omitted,
+                                             if description.omitted > 1 { "s" } else { "" },
+                                             description
+                                                 .omitted_groups
+                                                 .iter()
+                                                 .map(|group| self.code(&self.escape(group)))
+                                                 .collect::<Vec<String>>()
+                                                 .join(", ")));
+                }
+                answer
+            }
+            Payload::Devices {
+                ref name,
+                ref device_type,
+                page,
+                pages,
+                total,
+                ref devices,
+            } => {
+                let filters = format!("{}{}",
+                                      match *name {
+                                          Some(ref name) => format!(" named '{}'", self.escape(name)),
+                                          None => "".to_string(),
+                                      },
+                                      match *device_type {
+                                          Some(ref device_type) => format!(" of type '{}'", self.escape(device_type)),
+                                          None => "".to_string(),
+                                      });
+                if total == 0 {
+                    return format!("No device found{}.", filters);
+                }
+                let items = devices
+                    .iter()
+                    .map(|device| {
+                        let status = match device.connected {
+                            Some(true) => Some("online"),
+                            Some(false) => Some("offline"),
+                            None => None,
+                        };
+                        match (device.device_type.as_ref(), status) {
+                            (Some(device_type), Some(status)) => format!("{} ({}, {})", device.name, device_type, status),
+                            (Some(device_type), None) => format!("{} ({})", device.name, device_type),
+                            (None, Some(status)) => format!("{} ({})", device.name, status),
+                            (None, None) => device.name.clone(),
+                        }
+                    })
+                    .collect::<Vec<String>>();
+                format!("Devices{} ({}, page {} of {}):{}{}{}",
+                        filters,
+                        total,
+                        page,
+                        pages,
+                        self.line_break(),
+                        self.list_from(&items, (page - 1) * DEVICES_PER_PAGE + 1),
+                        if page < pages {
+                            format!("{}Say 'more' to see the next ones.", self.line_break())
+                        } else {
+                            "".to_string()
+                        })
+            }
+            Payload::DeviceTypes {
+                ref query,
+                total,
+                ref device_types,
+            } => {
+                format!("Device types matching '{}':{}{}{}",
+                        self.escape(query),
+                        self.line_break(),
+                        self.list(device_types),
+                        if total > device_types.len() {
+                            format!("{}... and {} more.", self.line_break(), total - device_types.len())
+                        } else {
+                            "".to_string()
+                        })
+            }
+            Payload::DeviceTypeNotFound { ref query } => format!("No device type found for '{}'.", self.escape(query)),
+            Payload::RequestFailed => "ARTIK Cloud couldn't complete the request, please try again later.".to_string(),
+            Payload::NotUnderstood { ref sentence } => {
+                format!("Unknown intent: {:?}",
+                        match *sentence {
+                            Some(ref sentence) => self.escape(sentence),
+                            None => "'no intent found'".to_string(),
+                        })
+            }
+        }
+    }
+}
+
+pub struct PlainText;
+impl Renderer for PlainText {
+    fn escape(&self, text: &str) -> String {
+        text.to_string()
+    }
+    fn strong(&self, text: &str) -> String {
+        text.to_string()
+    }
+    fn code(&self, text: &str) -> String {
+        text.to_string()
+    }
+    fn line_break(&self) -> &'static str {
+        "\n"
+    }
+}
+
+pub struct Html;
+impl Renderer for Html {
+    fn escape(&self, text: &str) -> String {
+        text.replace('&', "&amp;")
+            .replace('<', "&lt;")
+            .replace('>', "&gt;")
+            .replace('"', "&quot;")
+    }
+    fn strong(&self, text: &str) -> String {
+        format!("<b>{}</b>", text)
+    }
+    fn code(&self, text: &str) -> String {
+        format!("<code>{}</code>", text)
+    }
+    fn line_break(&self) -> &'static str {
+        "<br/>"
+    }
+    fn table(&self, rows: &[Vec<String>]) -> String {
+        format!("<table>{}</table>",
+                rows.iter()
+                    .map(|row| format!("<tr><td>{}</td></tr>", row.join("</td><td>")))
+                    .collect::<String>())
+    }
+}
+
+/// Slack's markup, see https://api.slack.com/reference/surfaces/formatting
+pub struct Mrkdwn;
+impl Renderer for Mrkdwn {
+    fn escape(&self, text: &str) -> String {
+        text.replace('&', "&amp;")
+            .replace('<', "&lt;")
+            .replace('>', "&gt;")
+    }
+    fn strong(&self, text: &str) -> String {
+        format!("*{}*", text)
+    }
+    fn code(&self, text: &str) -> String {
+        format!("`{}`", text)
+    }
+    fn line_break(&self) -> &'static str {
+        "\n"
+    }
+}
+
+/// A formatted message with a plain text summary, for platforms that show one in notifications.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Card {
+    pub summary: String,
+    pub body: String,
+}
+impl Card {
+    pub fn render<R: Renderer>(renderer: &R, message: &MessageToUser) -> Card {
+        Card {
+            summary: PlainText.render(message),
+            body: renderer.render(message),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sami::Intent;
+    use sami::alerts::Alert;
+    use sami::input::{Aggregation, Comparison, Period};
+    use sami::output::{MessageToUser, Status, Payload, FieldReading, History, Statistics, Aggregate, Description, DeviceSummary};
+
+    use super::*;
+
+    fn field_value() -> MessageToUser {
+        MessageToUser {
+            intent: Intent::GetField,
+            payload: Payload::FieldValue(FieldReading {
+                                             device: "Kitchen <sensor>".to_string(),
+                                             field: "temperature".to_string(),
+                                             value: "21.5".to_string(),
+                                             unit: None,
+                                             ts: None,
+                                             stale: false,
+                                         }),
+            status: Status::Info,
+        }
+    }
+
+    #[test]
+    fn renders_each_format() {
+        assert_eq!(PlainText.render(&field_value()), "Kitchen <sensor>'s temperature is 21.5.");
+        assert_eq!(Html.render(&field_value()),
+                   "Kitchen &lt;sensor&gt;'s <code>temperature</code> is <b>21.5</b>.");
+        assert_eq!(Card::render(&Mrkdwn, &field_value()),
+                   Card {
+                       summary: "Kitchen <sensor>'s temperature is 21.5.".to_string(),
+                       body: "Kitchen &lt;sensor&gt;'s `temperature` is *21.5*.".to_string(),
+                   });
+    }
+
+    #[test]
+    fn renders_unit_and_age_of_reading() {
+        let message = MessageToUser {
+            intent: Intent::GetField,
+            payload: Payload::FieldValue(FieldReading {
+                                             device: "Kitchen".to_string(),
+                                             field: "temperature".to_string(),
+                                             value: "21.5".to_string(),
+                                             unit: Some("°C".to_string()),
+                                             ts: Some(1_500_000_000_000),
+                                             stale: true,
+                                         }),
+            status: Status::Info,
+        };
+        assert_eq!(PlainText.render_at(&message, 1_500_000_000 + 180),
+                   "Kitchen's temperature is 21.5 °C (3 minutes ago). This reading may be outdated.");
+    }
+
+    #[test]
+    fn renders_history_statistics() {
+        let message = MessageToUser {
+            intent: Intent::GetHistory,
+            payload: Payload::History(History {
+                                          device: "Kitchen".to_string(),
+                                          field: "temperature".to_string(),
+                                          unit: Some("°C".to_string()),
+                                          period: Period {
+                                              from: 1_499_904_000_000,
+                                              to: 1_499_990_400_000,
+                                          },
+                                          count: 3,
+                                          statistics: Some(Statistics {
+                                                               min: 19.5,
+                                                               max: 22.0,
+                                                               average: 20.833333,
+                                                           }),
+                                          last_values: vec![],
+                                      }),
+            status: Status::Info,
+        };
+        assert_eq!(PlainText.render(&message),
+                   "Kitchen's temperature between 2017-07-13 00:00 UTC and 2017-07-14 00:00 UTC: \
+                    min 19.5 °C, max 22 °C, average 20.83 °C over 3 values.");
+    }
+
+    #[test]
+    fn renders_asked_aggregate_per_interval() {
+        let message = MessageToUser {
+            intent: Intent::GetAggregate,
+            payload: Payload::Aggregate(Aggregate {
+                                            device: "Meter".to_string(),
+                                            field: "power".to_string(),
+                                            unit: Some("W".to_string()),
+                                            period: Period {
+                                                from: 1_499_904_000_000,
+                                                to: 1_500_076_800_000,
+                                            },
+                                            aggregation: Some(Aggregation::Sum),
+                                            count: 48,
+                                            min: 0.0,
+                                            max: 300.0,
+                                            average: 125.0,
+                                            sum: 6000.0,
+                                            intervals: vec![(1_499_904_000_000, 2500.0), (1_499_990_400_000, 3500.0)],
+                                        }),
+            status: Status::Info,
+        };
+        assert_eq!(PlainText.render(&message),
+                   "The total of Meter's power between 2017-07-13 00:00 UTC and 2017-07-15 00:00 UTC is 6000 W over 48 values.\n\
+                    1. 2017-07-13 00:00 UTC: 2500 W\n\
+                    2. 2017-07-14 00:00 UTC: 3500 W");
+    }
+
+    #[test]
+    fn renders_device_description() {
+        let reading = |field: &str, value: &str, ts: Option<u64>| {
+            FieldReading {
+                device: "Kitchen".to_string(),
+                field: field.to_string(),
+                value: value.to_string(),
+                unit: None,
+                ts: ts,
+                stale: false,
+            }
+        };
+        let message = MessageToUser {
+            intent: Intent::DescribeDevice,
+            payload: Payload::DeviceDescription(Description {
+                                                    device: "Kitchen".to_string(),
+                                                    fields: vec![reading("state.door", "\"open\"", Some(1_500_000_000_000)),
+                                                                 reading("temperature", "21.5", None)],
+                                                    omitted: 3,
+                                                    omitted_groups: vec!["location".to_string()],
+                                                }),
+            status: Status::Info,
+        };
+        assert_eq!(Html.render_at(&message, 1_500_000_000 + 120),
+                   "Kitchen's latest values:<br/><table>\
+                    <tr><td><code>state.door</code></td><td><b>&quot;open&quot;</b></td><td>2 minutes ago</td></tr>\
+                    <tr><td><code>temperature</code></td><td><b>21.5</b></td><td></td></tr></table>\
+                    <br/>... and 3 more fields in <code>location</code>, ask for one by name.");
+        assert_eq!(shorten("abcdef", 4), "abc…");
+    }
+
+    #[test]
+    fn renders_page_of_devices() {
+        let message = MessageToUser {
+            intent: Intent::ListDevices,
+            payload: Payload::Devices {
+                name: None,
+                device_type: Some("sensor".to_string()),
+                page: 2,
+                pages: 3,
+                total: 25,
+                devices: vec![DeviceSummary {
+                                  name: "Kitchen sensor".to_string(),
+                                  device_type: Some("Temperature Sensor".to_string()),
+                                  connected: Some(true),
+                              },
+                              DeviceSummary {
+                                  name: "Porch".to_string(),
+                                  device_type: None,
+                                  connected: None,
+                              }],
+            },
+            status: Status::Info,
+        };
+        assert_eq!(PlainText.render(&message),
+                   "Devices of type 'sensor' (25, page 2 of 3):\n\
+                    11. Kitchen sensor (Temperature Sensor, online)\n\
+                    12. Porch\n\
+                    Say 'more' to see the next ones.");
+    }
+
+    #[test]
+    fn renders_alerts() {
+        let alert = Alert {
+            device_id: "d1".to_string(),
+            device: "Freezer".to_string(),
+            path: vec!["temperature".to_string()],
+            unit: Some("°C".to_string()),
+            comparison: Comparison::Above,
+            threshold: -10.0,
+            triggered: false,
+        };
+        let triggered = MessageToUser {
+            intent: Intent::CreateAlert,
+            payload: Payload::AlertTriggered {
+                alert: alert.clone(),
+                value: -4.5,
+            },
+            status: Status::ActionRequired,
+        };
+        assert_eq!(PlainText.render(&triggered),
+                   "Alert: Freezer's temperature is -4.5 °C, above -10 °C.");
+        let listed = MessageToUser {
+            intent: Intent::ListAlerts,
+            payload: Payload::Alerts(vec![alert]),
+            status: Status::Info,
+        };
+        assert_eq!(Html.render(&listed),
+                   "Alerts of this room:<br/>1. Freezer's temperature above -10 °C");
+    }
+
+    #[test]
+    fn relative_ages() {
+        assert_eq!(relative_age(5), "just now");
+        assert_eq!(relative_age(42), "42 seconds ago");
+        assert_eq!(relative_age(3600), "1 hour ago");
+        assert_eq!(relative_age(86400 * 3 + 5), "3 days ago");
+    }
+
+    #[test]
+    fn asks_which_device_on_ties() {
+        let message = MessageToUser {
+            intent: Intent::GetField,
+            payload: Payload::AmbiguousDevice {
+                indication: "lamp".to_string(),
+                candidates: vec!["Desk lamp".to_string(), "Floor lamp".to_string()],
+            },
+            status: Status::ActionRequired,
+        };
+        assert_eq!(PlainText.render(&message),
+                   "Several devices match 'lamp', which one do you mean?\n1. Desk lamp\n2. Floor lamp");
+    }
+
+    #[test]
+    fn lists_candidates_of_missing_device() {
+        let message = MessageToUser {
+            intent: Intent::GetField,
+            payload: Payload::DeviceNotFound {
+                indication: "lamp".to_string(),
+                candidates: vec!["Desk lamp".to_string(), "Floor lamp".to_string()],
+            },
+            status: Status::Error,
+        };
+        assert_eq!(Mrkdwn.render(&message),
+                   "No device found for 'lamp'. Did you mean *Desk lamp*, *Floor lamp*?");
+    }
+}
@@ -0,0 +1,470 @@
+use futures::future::*;
+use regex::{Regex, Captures};
+use time;
+
+use akc;
+
+use sami::Intent;
+use sami::input::{Aggregation, Comparison, NlpBackend, NlpError, NlpResponse, Period};
+
+lazy_static! {
+    static ref GET_SELF: Regex = Regex::new(r"^(?:who ?am i|who is connected|what is my (?:name|account))$").unwrap();
+    static ref LOGOUT: Regex = Regex::new(r"^(?:log ?out|sign ?out|disconnect)(?: me)?$").unwrap();
+    static ref GET_FIELD_OF: Regex = Regex::new(r"^(?:what(?:'s| is)|get|show(?: me)?|give me) (?:the )?(?P<field>[\w.]+(?: [\w.]+)*?) (?:of|for|from|on) (?P<device>.+)$").unwrap();
+    static ref GET_FIELD: Regex = Regex::new(r"^(?:what(?:'s| is)|get|show(?: me)?|give me) (?P<device>.+?)(?:'s)? (?P<field>[\w.]+)$").unwrap();
+    static ref SET_FIELD_OF: Regex = Regex::new(r"^(?:set|change|put) (?:the )?(?P<field>[\w.]+(?: [\w.]+)*?) (?:of|for|on) (?P<device>.+?) to (?P<value>.+)$").unwrap();
+    static ref SET_FIELD: Regex = Regex::new(r"^(?:set|change|put) (?P<device>.+?)(?:'s)? (?P<field>[\w.]+) to (?P<value>.+)$").unwrap();
+    static ref HISTORY_OF: Regex = Regex::new(&format!(r"^(?:what was|how was|show(?: me)?) (?:the )?(?P<field>[\w.]+(?: [\w.]+)*?) (?:of|for|from|on) (?P<device>.+?) {}$", PERIOD)).unwrap();
+    static ref HISTORY: Regex = Regex::new(&format!(r"^(?:what was|how was) (?P<device>.+?)(?:'s)? (?P<field>[\w.]+) {}$", PERIOD)).unwrap();
+    static ref AGGREGATE_OF: Regex = Regex::new(&format!(r"{}(?P<field>[\w.]+(?: [\w.]+)*?) (?:of|for|from|on) (?P<device>.+?){}(?: {})?$", AGGREGATION, INTERVAL, PERIOD)).unwrap();
+    static ref AGGREGATE: Regex = Regex::new(&format!(r"{}(?:(?P<device>.+?)(?:'s)? )?(?P<field>[\w.]+){}(?: {})?$", AGGREGATION, INTERVAL, PERIOD)).unwrap();
+    static ref CREATE_ALERT_OF: Regex = Regex::new(&format!(r"^(?:alert|notify|tell|warn) (?:me |us )?when (?:the )?(?P<field>[\w.]+(?: [\w.]+)*?) (?:of|for|on) (?P<device>.+?){}$", CONDITION)).unwrap();
+    static ref CREATE_ALERT: Regex = Regex::new(&format!(r"^(?:alert|notify|tell|warn) (?:me |us )?when (?P<device>.+?)(?:'s)? (?P<field>[\w.]+){}$", CONDITION)).unwrap();
+    static ref LIST_ALERTS: Regex = Regex::new(r"^(?:(?:list|show)(?: me)?(?: the| all)?(?: my| our)? alerts|what are (?:the|my|our) alerts)$").unwrap();
+    static ref DELETE_ALERT: Regex = Regex::new(r"^(?:delete|remove|cancel|stop) alert (?:number |#)?(?P<value>\d+)$").unwrap();
+    static ref WATCH_OF: Regex = Regex::new(&format!(r"^(?:watch|follow|monitor) (?:the )?(?P<field>[\w.]+(?: [\w.]+)*?) (?:of|for|on) (?P<device>.+?){}$", DURATION)).unwrap();
+    static ref WATCH: Regex = Regex::new(&format!(r"^(?:watch|follow|monitor) (?P<device>.+?)(?:'s)? (?P<field>[\w.]+){}$", DURATION)).unwrap();
+    static ref STOP_WATCHING: Regex = Regex::new(r"^(?:stop|cancel|quit) (?:watching|following|monitoring)(?: it| that)?$").unwrap();
+    static ref DESCRIBE_DEVICE: Regex = Regex::new(r"^(?:describe|(?:show|tell)(?: me)?(?: everything| all)? about|what do you know about|(?:show|give|list)(?: me)? (?:all|every) (?:the )?(?:values|fields) (?:of|for|from|on)) (?P<device>.+)$").unwrap();
+    static ref LIST_DEVICES: Regex = Regex::new(r"^(?:what devices do (?:i|we) have|(?:list|show)(?: me)?(?: all)?(?: my| our| the)? devices)(?: (?:named|called|matching) (?P<device>.+?))?(?: of type (?P<device_type>.+))?$").unwrap();
+    static ref LIST_DEVICES_OF_TYPE: Regex = Regex::new(r"^(?:list|show)(?: me)?(?: all)?(?: my| our| the)? (?P<device_type>.+?) devices$").unwrap();
+    static ref NEXT_PAGE: Regex = Regex::new(r"^(?:more|next(?: page)?|show more)$").unwrap();
+    static ref LAST_PERIOD: Regex = Regex::new(r"the last (?:(?P<count>\d+) )?(?P<unit>minute|hour|day|week)s?$").unwrap();
+    static ref FOLLOW_UP_DEVICE: Regex = Regex::new(r"^(?:and|what about|how about) (?:for|of|on) (?P<device>.+)$").unwrap();
+    static ref FOLLOW_UP_FIELD: Regex = Regex::new(r"^(?:and|what about|how about) (?:the )?(?P<field>[\w.]+)$").unwrap();
+}
+
+const PERIOD: &'static str = r"(?P<period>yesterday|today|this week|(?:(?:in|over|during) )?the last (?:\d+ )?(?:minute|hour|day|week)s?)";
+
+const AGGREGATION: &'static str = r"^(?:what(?:'s| is| was)|show(?: me)?|give me)? ?(?:the )?(?P<aggregation>average|mean|minimum|min|lowest|maximum|max|highest|total|sum|number of values)(?: of)? (?:the )?";
+const INTERVAL: &'static str = r"(?: per (?P<interval>minute|hour|day|month|year))?";
+
+const CONDITION: &'static str = r" (?:goes |gets |is |rises |drops |falls )?(?P<comparison>above|over|higher than|more than|exceeds|below|under|lower than|less than) (?P<value>-?\d+(?:\.\d+)?)";
+
+const DURATION: &'static str = r"(?: for (?:(?P<count>\d+)|an?) (?P<unit>minute|hour)s?)?";
+
+const MINUTE: u64 = 60 * 1000;
+const DAY: u64 = 24 * 60 * MINUTE;
+
+/// Turns "yesterday" or "the last 3 hours" into a period, `now` being in milliseconds since epoch.
+pub fn period_at(text: &str, now: u64) -> Option<Period> {
+    let midnight = now - now % DAY;
+    match text {
+        "today" => Some(Period { from: midnight, to: now }),
+        "yesterday" => Some(Period { from: midnight - DAY, to: midnight }),
+        "this week" => {
+            // 1970-01-01 was a Thursday
+            let days_since_monday = (now / DAY + 3) % 7;
+            Some(Period {
+                     from: midnight - days_since_monday * DAY,
+                     to: now,
+                 })
+        }
+        text => {
+            LAST_PERIOD
+                .captures(text)
+                .map(|captures| {
+                    let count = captures
+                        .name("count")
+                        .and_then(|count| count.as_str().parse::<u64>().ok())
+                        .unwrap_or(1);
+                    let unit = match captures.name("unit").map(|unit| unit.as_str()) {
+                        Some("minute") => MINUTE,
+                        Some("hour") => 60 * MINUTE,
+                        Some("day") => DAY,
+                        _ => 7 * DAY,
+                    };
+                    Period {
+                        from: now.saturating_sub(count * unit),
+                        to: now,
+                    }
+                })
+        }
+    }
+}
+
+const STOP_WORDS: [&'static str; 4] = ["the", "my", "a", "an"];
+
+/// Offline keyword grammar, understanding a fixed set of phrasings.
+pub struct Rules {}
+
+impl Rules {
+    pub fn new() -> Rules {
+        Rules {}
+    }
+
+    pub fn parse(&self, query: &str) -> NlpResponse {
+        let now = time::get_time();
+        self.parse_at(query, now.sec as u64 * 1000 + now.nsec as u64 / 1_000_000)
+    }
+
+    /// Parses `query` as if sent at `now`, in milliseconds since epoch.
+    pub fn parse_at(&self, query: &str, now: u64) -> NlpResponse {
+        let query = query
+            .trim()
+            .trim_right_matches(|c: char| c == '?' || c == '.' || c == '!')
+            .to_lowercase();
+        if GET_SELF.is_match(&query) {
+            return NlpResponse {
+                       intent: Intent::GetSelf,
+                       ..Default::default()
+                   };
+        }
+        if LOGOUT.is_match(&query) {
+            return NlpResponse {
+                       intent: Intent::Logout,
+                       ..Default::default()
+                   };
+        }
+        if let Some(captures) = CREATE_ALERT_OF.captures(&query).or_else(|| CREATE_ALERT.captures(&query)) {
+            return NlpResponse {
+                       intent: Intent::CreateAlert,
+                       device: device_indications(&captures),
+                       field: captures.name("field").map(|field| field.as_str().to_string()),
+                       value: captures.name("value").map(|value| value.as_str().to_string()),
+                       comparison: captures.name("comparison").map(|comparison| match comparison.as_str() {
+                           "below" | "under" | "lower than" | "less than" => Comparison::Below,
+                           _ => Comparison::Above,
+                       }),
+                       ..Default::default()
+                   };
+        }
+        if LIST_ALERTS.is_match(&query) {
+            return NlpResponse {
+                       intent: Intent::ListAlerts,
+                       ..Default::default()
+                   };
+        }
+        if let Some(captures) = DELETE_ALERT.captures(&query) {
+            return NlpResponse {
+                       intent: Intent::DeleteAlert,
+                       value: captures.name("value").map(|value| value.as_str().to_string()),
+                       ..Default::default()
+                   };
+        }
+        if STOP_WATCHING.is_match(&query) {
+            return NlpResponse {
+                       intent: Intent::StopWatching,
+                       ..Default::default()
+                   };
+        }
+        if let Some(captures) = DESCRIBE_DEVICE.captures(&query) {
+            return NlpResponse {
+                       intent: Intent::DescribeDevice,
+                       device: device_indications(&captures),
+                       ..Default::default()
+                   };
+        }
+        if let Some(captures) = LIST_DEVICES.captures(&query).or_else(|| LIST_DEVICES_OF_TYPE.captures(&query)) {
+            return NlpResponse {
+                       intent: Intent::ListDevices,
+                       device: device_indications(&captures),
+                       device_type: captures.name("device_type").map(|device_type| device_type.as_str().to_string()),
+                       ..Default::default()
+                   };
+        }
+        if NEXT_PAGE.is_match(&query) {
+            return NlpResponse {
+                       intent: Intent::NextPage,
+                       meta: Some(vec![query]),
+                       ..Default::default()
+                   };
+        }
+        if let Some(captures) = WATCH_OF.captures(&query).or_else(|| WATCH.captures(&query)) {
+            let count = captures
+                .name("count")
+                .and_then(|count| count.as_str().parse::<u64>().ok())
+                .unwrap_or(1);
+            return NlpResponse {
+                       intent: Intent::Watch,
+                       device: device_indications(&captures),
+                       field: captures.name("field").map(|field| field.as_str().to_string()),
+                       duration: captures.name("unit").map(|unit| match unit.as_str() {
+                                                              "hour" => count * 60 * 60,
+                                                              _ => count * 60,
+                                                          }),
+                       ..Default::default()
+                   };
+        }
+        if let Some(captures) = AGGREGATE_OF.captures(&query).or_else(|| AGGREGATE.captures(&query)) {
+            return NlpResponse {
+                       intent: Intent::GetAggregate,
+                       device: device_indications(&captures),
+                       field: captures.name("field").map(|field| field.as_str().to_string()),
+                       period: captures.name("period").and_then(|period| period_at(period.as_str(), now)),
+                       aggregation: captures.name("aggregation").map(|aggregation| match aggregation.as_str() {
+                           "minimum" | "min" | "lowest" => Aggregation::Minimum,
+                           "maximum" | "max" | "highest" => Aggregation::Maximum,
+                           "total" | "sum" => Aggregation::Sum,
+                           "number of values" => Aggregation::Count,
+                           _ => Aggregation::Average,
+                       }),
+                       interval: captures.name("interval").map(|interval| match interval.as_str() {
+                           "minute" => akc::message::Interval::Minute,
+                           "hour" => akc::message::Interval::Hour,
+                           "month" => akc::message::Interval::Month,
+                           "year" => akc::message::Interval::Year,
+                           _ => akc::message::Interval::Day,
+                       }),
+                       ..Default::default()
+                   };
+        }
+        if let Some(captures) = HISTORY_OF.captures(&query).or_else(|| HISTORY.captures(&query)) {
+            return NlpResponse {
+                       intent: Intent::GetHistory,
+                       device: device_indications(&captures),
+                       field: captures.name("field").map(|field| field.as_str().to_string()),
+                       period: captures.name("period").and_then(|period| period_at(period.as_str(), now)),
+                       ..Default::default()
+                   };
+        }
+        if let Some(captures) = SET_FIELD_OF.captures(&query).or_else(|| SET_FIELD.captures(&query)) {
+            return NlpResponse {
+                       intent: Intent::SetField,
+                       device: device_indications(&captures),
+                       field: captures.name("field").map(|field| field.as_str().to_string()),
+                       value: captures.name("value").map(|value| value.as_str().trim().to_string()),
+                       ..Default::default()
+                   };
+        }
+        if let Some(captures) = GET_FIELD_OF.captures(&query).or_else(|| GET_FIELD.captures(&query)) {
+            return NlpResponse {
+                       intent: Intent::GetField,
+                       device: device_indications(&captures),
+                       field: captures.name("field").map(|field| field.as_str().to_string()),
+                       ..Default::default()
+                   };
+        }
+        // follow-ups leave the missing slots to the conversation
+        if let Some(captures) = FOLLOW_UP_DEVICE.captures(&query) {
+            return NlpResponse {
+                       intent: Intent::GetField,
+                       device: device_indications(&captures),
+                       ..Default::default()
+                   };
+        }
+        if let Some(captures) = FOLLOW_UP_FIELD.captures(&query) {
+            return NlpResponse {
+                       intent: Intent::GetField,
+                       field: captures.name("field").map(|field| field.as_str().to_string()),
+                       ..Default::default()
+                   };
+        }
+        NlpResponse {
+            meta: Some(vec![query]),
+            ..Default::default()
+        }
+    }
+}
+
+fn device_indications(captures: &Captures) -> Option<Vec<String>> {
+    captures
+        .name("device")
+        .map(|device| {
+                 device
+                     .as_str()
+                     .split_whitespace()
+                     .filter(|word| !STOP_WORDS.iter().any(|stop_word| stop_word == word))
+                     .map(|word| word.to_string())
+                     .collect::<Vec<String>>()
+             })
+        .and_then(|words| if words.is_empty() { None } else { Some(words) })
+}
+
+impl NlpBackend for Rules {
+    fn understand(&self, query: &str) -> Box<Future<Item = NlpResponse, Error = NlpError> + Send> {
+        ok(NlpResponse {
+               query: query.to_string(),
+               ..self.parse(query)
+           })
+                .boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use akc;
+
+    use sami::Intent;
+    use sami::input::{Aggregation, Comparison, Period};
+    use super::{Rules, period_at};
+
+    #[test]
+    fn understands_get_self_and_logout() {
+        assert_eq!(Rules::new().parse("Who am I?").intent, Intent::GetSelf);
+        assert_eq!(Rules::new().parse("log out").intent, Intent::Logout);
+    }
+
+    #[test]
+    fn understands_get_field() {
+        let response = Rules::new().parse("what is the temperature of the kitchen sensor?");
+        assert_eq!(response.intent, Intent::GetField);
+        assert_eq!(response.device, Some(vec!["kitchen".to_string(), "sensor".to_string()]));
+        assert_eq!(response.field, Some("temperature".to_string()));
+
+        let response = Rules::new().parse("what's the kitchen sensor's humidity");
+        assert_eq!(response.intent, Intent::GetField);
+        assert_eq!(response.device, Some(vec!["kitchen".to_string(), "sensor".to_string()]));
+        assert_eq!(response.field, Some("humidity".to_string()));
+
+        let response = Rules::new().parse("what is the gps lat of the tracker");
+        assert_eq!(response.device, Some(vec!["tracker".to_string()]));
+        assert_eq!(response.field, Some("gps lat".to_string()));
+    }
+
+    #[test]
+    fn understands_set_field() {
+        let response = Rules::new().parse("set the thermostat temperature to 21");
+        assert_eq!(response.intent, Intent::SetField);
+        assert_eq!(response.device, Some(vec!["thermostat".to_string()]));
+        assert_eq!(response.field, Some("temperature".to_string()));
+        assert_eq!(response.value, Some("21".to_string()));
+    }
+
+    #[test]
+    fn understands_follow_ups() {
+        let response = Rules::new().parse("and the humidity?");
+        assert_eq!(response.intent, Intent::GetField);
+        assert_eq!(response.device, None);
+        assert_eq!(response.field, Some("humidity".to_string()));
+
+        let response = Rules::new().parse("what about for the bedroom sensor");
+        assert_eq!(response.intent, Intent::GetField);
+        assert_eq!(response.device, Some(vec!["bedroom".to_string(), "sensor".to_string()]));
+        assert_eq!(response.field, None);
+    }
+
+    #[test]
+    fn understands_history() {
+        let now = 1_500_000_000_000;
+        let response = Rules::new().parse_at("what was the temperature of the kitchen sensor yesterday?", now);
+        assert_eq!(response.intent, Intent::GetHistory);
+        assert_eq!(response.device, Some(vec!["kitchen".to_string(), "sensor".to_string()]));
+        assert_eq!(response.field, Some("temperature".to_string()));
+        assert_eq!(response.period, period_at("yesterday", now));
+
+        let response = Rules::new().parse_at("how was the humidity over the last 3 hours", now);
+        assert_eq!(response.intent, Intent::GetHistory);
+        assert_eq!(response.device, None);
+        assert_eq!(response.period,
+                   Some(Period {
+                            from: now - 3 * 60 * 60 * 1000,
+                            to: now,
+                        }));
+    }
+
+    #[test]
+    fn understands_aggregates() {
+        let now = 1_500_000_000_000;
+        let response = Rules::new().parse_at("What was the average power consumption this week?", now);
+        assert_eq!(response.intent, Intent::GetAggregate);
+        assert_eq!(response.aggregation, Some(Aggregation::Average));
+        assert_eq!(response.device, Some(vec!["power".to_string()]));
+        assert_eq!(response.field, Some("consumption".to_string()));
+        assert_eq!(response.period, period_at("this week", now));
+
+        let response = Rules::new().parse_at("what was the total power of the meter per day this week", now);
+        assert_eq!(response.intent, Intent::GetAggregate);
+        assert_eq!(response.aggregation, Some(Aggregation::Sum));
+        assert_eq!(response.device, Some(vec!["meter".to_string()]));
+        assert_eq!(response.field, Some("power".to_string()));
+        assert_eq!(response.interval, Some(akc::message::Interval::Day));
+
+        let response = Rules::new().parse_at("what is the max temperature", now);
+        assert_eq!(response.aggregation, Some(Aggregation::Maximum));
+        assert_eq!(response.device, None);
+        assert_eq!(response.period, None);
+    }
+
+    #[test]
+    fn understands_alerts() {
+        let response = Rules::new().parse("alert me when the freezer temperature goes above -10");
+        assert_eq!(response.intent, Intent::CreateAlert);
+        assert_eq!(response.device, Some(vec!["freezer".to_string()]));
+        assert_eq!(response.field, Some("temperature".to_string()));
+        assert_eq!(response.comparison, Some(Comparison::Above));
+        assert_eq!(response.value, Some("-10".to_string()));
+
+        let response = Rules::new().parse("notify us when the humidity of the cellar drops below 40.5");
+        assert_eq!(response.device, Some(vec!["cellar".to_string()]));
+        assert_eq!(response.comparison, Some(Comparison::Below));
+        assert_eq!(response.value, Some("40.5".to_string()));
+
+        assert_eq!(Rules::new().parse("show me all alerts").intent, Intent::ListAlerts);
+        let response = Rules::new().parse("delete alert #2");
+        assert_eq!(response.intent, Intent::DeleteAlert);
+        assert_eq!(response.value, Some("2".to_string()));
+    }
+
+    #[test]
+    fn understands_watch() {
+        let response = Rules::new().parse("watch the freezer temperature for 15 minutes");
+        assert_eq!(response.intent, Intent::Watch);
+        assert_eq!(response.device, Some(vec!["freezer".to_string()]));
+        assert_eq!(response.field, Some("temperature".to_string()));
+        assert_eq!(response.duration, Some(15 * 60));
+
+        let response = Rules::new().parse("monitor the power of the meter for an hour");
+        assert_eq!(response.device, Some(vec!["meter".to_string()]));
+        assert_eq!(response.duration, Some(60 * 60));
+
+        assert_eq!(Rules::new().parse("Stop watching!").intent, Intent::StopWatching);
+    }
+
+    #[test]
+    fn understands_device_description() {
+        let response = Rules::new().parse("Show me everything about the kitchen sensor");
+        assert_eq!(response.intent, Intent::DescribeDevice);
+        assert_eq!(response.device, Some(vec!["kitchen".to_string(), "sensor".to_string()]));
+        assert_eq!(Rules::new().parse("describe the freezer").intent, Intent::DescribeDevice);
+    }
+
+    #[test]
+    fn understands_device_listing() {
+        let response = Rules::new().parse("show me all my devices");
+        assert_eq!(response.intent, Intent::ListDevices);
+        assert_eq!(response.device, None);
+        assert_eq!(response.device_type, None);
+
+        let response = Rules::new().parse("list devices named kitchen of type thermostat");
+        assert_eq!(response.device, Some(vec!["kitchen".to_string()]));
+        assert_eq!(response.device_type, Some("thermostat".to_string()));
+
+        let response = Rules::new().parse("List my smart plug devices");
+        assert_eq!(response.intent, Intent::ListDevices);
+        assert_eq!(response.device_type, Some("smart plug".to_string()));
+
+        assert_eq!(Rules::new().parse("More").intent, Intent::NextPage);
+    }
+
+    #[test]
+    fn computes_periods() {
+        // Friday 2017-07-14 02:40:00 UTC
+        let now = 1_500_000_000_000;
+        let midnight = 1_499_990_400_000;
+        assert_eq!(period_at("today", now), Some(Period { from: midnight, to: now }));
+        assert_eq!(period_at("yesterday", now),
+                   Some(Period {
+                            from: midnight - 86_400_000,
+                            to: midnight,
+                        }));
+        assert_eq!(period_at("this week", now),
+                   Some(Period {
+                            from: midnight - 4 * 86_400_000,
+                            to: now,
+                        }));
+        assert_eq!(period_at("during the last day", now),
+                   Some(Period {
+                            from: now - 86_400_000,
+                            to: now,
+                        }));
+        assert_eq!(period_at("last year", now), None);
+    }
+
+    #[test]
+    fn unknown_sentences_are_kept_as_meta() {
+        let response = Rules::new().parse("sing me a song");
+        assert_eq!(response.intent, Intent::Unknown);
+        assert_eq!(response.meta, Some(vec!["sing me a song".to_string()]));
+    }
+}
@@ -0,0 +1,412 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering, ATOMIC_USIZE_INIT};
+use std::time::Duration;
+
+use futures::{future, Future, Stream};
+use futures::sync::oneshot;
+
+use oauth2;
+use akc;
+
+use sami::Intent;
+use sami::output::{Deliver, MessageToUser, Payload, FieldReading, Status};
+
+use timer;
+
+use CPU_POOL;
+
+/// Values arriving faster than this, in seconds, wait for the end of the interval to be posted.
+const MIN_INTERVAL: u64 = 10;
+
+/// Resolves once a duration has elapsed.
+type Delay = Arc<Fn(Duration) -> Box<Future<Item = (), Error = ()> + Send> + Send + Sync>;
+
+#[derive(Debug, Clone)]
+pub struct Watched {
+    pub device_id: String,
+    pub device: String,
+    pub path: Vec<String>,
+    pub unit: Option<String>,
+}
+
+struct Watch {
+    id: usize,
+    watched: Watched,
+    /// Dropping it stops the watch.
+    _cancel: oneshot::Sender<()>,
+}
+
+/// Posts at most a value per interval, the latest value that arrived during an interval being posted when it ends.
+#[derive(Default)]
+struct Throttle {
+    posted: Option<String>,
+    /// Set from the time a value is posted until the end of its interval.
+    waiting: bool,
+    pending: Option<(String, MessageToUser)>,
+}
+impl Throttle {
+    /// What to post right away of a new value, an interval starting when there is something.
+    fn value(&mut self, value: String, message: MessageToUser) -> Option<MessageToUser> {
+        if self.pending.as_ref().map(|&(ref pending, _)| *pending == value).unwrap_or(false) {
+            return None;
+        }
+        if self.posted.as_ref() == Some(&value) {
+            // back to the posted value, what was waiting is not worth posting anymore
+            self.pending = None;
+            return None;
+        }
+        if self.waiting {
+            self.pending = Some((value, message));
+            return None;
+        }
+        self.posted = Some(value);
+        self.waiting = true;
+        Some(message)
+    }
+
+    /// What to post at the end of an interval, a new interval starting when there is something.
+    fn interval_ended(&mut self) -> Option<MessageToUser> {
+        match self.pending.take() {
+            Some((value, message)) => {
+                self.posted = Some(value);
+                Some(message)
+            }
+            None => {
+                self.waiting = false;
+                None
+            }
+        }
+    }
+}
+
+static NEXT_ID: AtomicUsize = ATOMIC_USIZE_INIT;
+
+lazy_static! {
+    static ref WATCHES: Arc<Mutex<HashMap<String, Watch>>> = Arc::new(Mutex::new(HashMap::new()));
+}
+
+/// Posts the new values of a field to the room for `duration`, replacing the previous watch of the room.
+pub fn start(context: &str, akc_token: oauth2::Token, watched: Watched, duration: Duration, deliver: Deliver) {
+    let readings = akc::Akc::live(akc_token, akc::live::LiveFilter::Devices(vec![watched.device_id.clone()]));
+    watch(context,
+          readings,
+          watched,
+          timer::sleep(duration),
+          Arc::new(timer::sleep),
+          deliver);
+}
+
+fn watch<S>(context: &str,
+            readings: S,
+            watched: Watched,
+            expiry: Box<Future<Item = (), Error = ()> + Send>,
+            delay: Delay,
+            deliver: Deliver)
+    where S: Stream<Item = akc::message::NormalizedMessage, Error = akc::error::AkcClientError> + Send + 'static
+{
+    let id = NEXT_ID.fetch_add(1, Ordering::SeqCst);
+    let (cancel, cancelled) = oneshot::channel::<()>();
+    WATCHES
+        .lock()
+        .unwrap()
+        .insert(context.to_string(),
+                Watch {
+                    id: id,
+                    watched: watched.clone(),
+                    _cancel: cancel,
+                });
+
+    let throttle = Arc::new(Mutex::new(Throttle::default()));
+    let context_for_readings = context.to_string();
+    let deliver_readings = deliver.clone();
+    let context_for_failure = context.to_string();
+    let deliver_failure = deliver.clone();
+    let watching = readings
+        .for_each(move |message| {
+            let value = match message.field(&watched.path) {
+                Some(value) => value.to_string(),
+                None => return future::ok(()).boxed(),
+            };
+            let reading = MessageToUser {
+                intent: Intent::Watch,
+                payload: Payload::FieldValue(FieldReading {
+                                                 device: watched.device.clone(),
+                                                 field: watched.path.join("."),
+                                                 value: value.clone(),
+                                                 unit: watched.unit.clone(),
+                                                 ts: Some(message.ts),
+                                                 stale: false,
+                                             }),
+                status: Status::Info,
+            };
+            let posted = throttle.lock().unwrap().value(value, reading);
+            match posted {
+                Some(reading) => {
+                    end_interval_later(context_for_readings.clone(), id, throttle.clone(), delay.clone(), deliver_readings.clone());
+                    // a room that can't be reached is not a reason to stop watching
+                    deliver_readings(reading)
+                        .then(|_| Ok(()))
+                        .boxed()
+                }
+                None => future::ok(()).boxed(),
+            }
+        })
+        .or_else(move |err| {
+            warn!("error watching device: {:?}", err);
+            match remove_if_current(&context_for_failure, id) {
+                Some(watch) => deliver_failure(failed(watch.watched)),
+                None => future::ok(()).boxed(),
+            }
+        });
+    let watching = watching
+        .select(cancelled.map_err(|_| ()))
+        .then(|_| Ok::<(), ()>(()));
+    CPU_POOL.spawn(watching).forget();
+
+    let context = context.to_string();
+    let expiring = expiry.and_then(move |_| match remove_if_current(&context, id) {
+                                       Some(watch) => deliver(stopped(watch.watched)),
+                                       None => future::ok(()).boxed(),
+                                   });
+    CPU_POOL.spawn(expiring).forget();
+}
+
+/// Ends the interval of the value just posted, posting the value that arrived meanwhile if the watch `id` is still
+/// the one of the room.
+fn end_interval_later(context: String, id: usize, throttle: Arc<Mutex<Throttle>>, delay: Delay, deliver: Deliver) {
+    let ending = delay(Duration::from_secs(MIN_INTERVAL)).and_then(move |_| {
+        if WATCHES.lock().unwrap().get(&context).map(|watch| watch.id) != Some(id) {
+            return future::ok(()).boxed();
+        }
+        let posted = throttle.lock().unwrap().interval_ended();
+        match posted {
+            Some(reading) => {
+                end_interval_later(context.clone(), id, throttle, delay, deliver.clone());
+                deliver(reading)
+            }
+            None => future::ok(()).boxed(),
+        }
+    });
+    CPU_POOL.spawn(ending).forget();
+}
+
+/// Removes the watch of the room if it is still the watch `id`.
+fn remove_if_current(context: &str, id: usize) -> Option<Watch> {
+    let mut watches = WATCHES.lock().unwrap();
+    match watches.get(context).map(|watch| watch.id) {
+        Some(watch_id) if watch_id == id => watches.remove(context),
+        _ => None,
+    }
+}
+
+/// Stops the watch of the room, returning what was watched.
+pub fn stop(context: &str) -> Option<Watched> {
+    WATCHES
+        .lock()
+        .unwrap()
+        .remove(context)
+        .map(|watch| watch.watched)
+}
+
+pub fn stopped(watched: Watched) -> MessageToUser {
+    MessageToUser {
+        intent: Intent::StopWatching,
+        payload: Payload::WatchStopped {
+            device: watched.device,
+            field: watched.path.join("."),
+        },
+        status: Status::Confirmation,
+    }
+}
+
+fn failed(watched: Watched) -> MessageToUser {
+    MessageToUser {
+        intent: Intent::Watch,
+        payload: Payload::WatchFailed {
+            device: watched.device,
+            field: watched.path.join("."),
+        },
+        status: Status::Error,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{mpsc, Arc, Mutex};
+    use std::time::Duration;
+
+    use futures::{future, Future, Poll, Sink, Stream};
+    use futures::sync::{mpsc as stream, oneshot};
+    use serde_json;
+
+    use akc::error::AkcClientError;
+    use akc::message::NormalizedMessage;
+
+    use sami::Intent;
+    use sami::output::{MessageToUser, Payload, FieldReading, Status};
+
+    use super::*;
+
+    type Readings = stream::Sender<Result<NormalizedMessage, AkcClientError>>;
+
+    /// Readings of a watch, telling when the watch lets go of them.
+    struct Tracked<S> {
+        readings: S,
+        dropped: mpsc::Sender<()>,
+    }
+    impl<S: Stream> Stream for Tracked<S> {
+        type Item = S::Item;
+        type Error = S::Error;
+
+        fn poll(&mut self) -> Poll<Option<S::Item>, S::Error> {
+            self.readings.poll()
+        }
+    }
+    impl<S> Drop for Tracked<S> {
+        fn drop(&mut self) {
+            let _ = self.dropped.send(());
+        }
+    }
+
+    /// A watch of `context` whose expiry is triggered by the test, and whose intervals never end.
+    struct Test {
+        readings: Option<Readings>,
+        expire: Option<oneshot::Sender<()>>,
+        delivered: mpsc::Receiver<String>,
+        dropped: mpsc::Receiver<()>,
+    }
+
+    fn start(context: &str) -> Test {
+        let (readings, received) = stream::channel(16);
+        let (dropped, watch_dropped) = mpsc::channel();
+        let received = Tracked {
+            readings: received
+                .map_err(|_| AkcClientError::InternalError("no more readings".to_string()))
+                .and_then(|reading| reading),
+            dropped: dropped,
+        };
+        let (expire, expired) = oneshot::channel();
+        let (deliveries, delivered) = mpsc::channel();
+        let deliveries = Mutex::new(deliveries);
+        watch(context,
+              received,
+              Watched {
+                  device_id: "d1".to_string(),
+                  device: "Freezer".to_string(),
+                  path: vec!["temperature".to_string()],
+                  unit: None,
+              },
+              expired.map_err(|_| ()).boxed(),
+              Arc::new(|_: Duration| future::empty::<(), ()>().boxed()),
+              Arc::new(move |message: MessageToUser| {
+                           let _ = deliveries.lock().unwrap().send(describe(&message));
+                           future::ok::<(), ()>(()).boxed()
+                       }));
+        Test {
+            readings: Some(readings),
+            expire: Some(expire),
+            delivered: delivered,
+            dropped: watch_dropped,
+        }
+    }
+
+    impl Test {
+        fn send(&mut self, reading: Result<NormalizedMessage, AkcClientError>) {
+            self.readings = Some(self.readings.take().unwrap().send(reading).wait().unwrap());
+        }
+
+        fn next_delivered(&self) -> String {
+            self.delivered.recv_timeout(Duration::from_secs(5)).unwrap()
+        }
+    }
+
+    fn describe(message: &MessageToUser) -> String {
+        match message.payload {
+            Payload::FieldValue(ref reading) => reading.value.clone(),
+            Payload::WatchStopped { .. } => "stopped".to_string(),
+            Payload::WatchFailed { .. } => "failed".to_string(),
+            _ => "unexpected".to_string(),
+        }
+    }
+
+    fn reading(temperature: f64) -> Result<NormalizedMessage, AkcClientError> {
+        Ok(NormalizedMessage {
+               mid: "m1".to_string(),
+               sdid: "d1".to_string(),
+               ts: 1_500_000_000_000,
+               data: serde_json::from_str(&format!(r#"{{"temperature": {}}}"#, temperature)).unwrap(),
+           })
+    }
+
+    fn message(value: &str) -> MessageToUser {
+        MessageToUser {
+            intent: Intent::Watch,
+            payload: Payload::FieldValue(FieldReading {
+                                             device: "Freezer".to_string(),
+                                             field: "temperature".to_string(),
+                                             value: value.to_string(),
+                                             unit: None,
+                                             ts: None,
+                                             stale: false,
+                                         }),
+            status: Status::Info,
+        }
+    }
+
+    fn post(throttle: &mut Throttle, value: &str) -> Option<String> {
+        throttle.value(value.to_string(), message(value)).map(|message| describe(&message))
+    }
+
+    fn end(throttle: &mut Throttle) -> Option<String> {
+        throttle.interval_ended().map(|message| describe(&message))
+    }
+
+    #[test]
+    fn posts_latest_value_at_end_of_interval() {
+        let mut throttle = Throttle::default();
+        assert_eq!(post(&mut throttle, "-18"), Some("-18".to_string()));
+        assert_eq!(post(&mut throttle, "-17"), None);
+        assert_eq!(post(&mut throttle, "-16"), None);
+        assert_eq!(end(&mut throttle), Some("-16".to_string()));
+        assert_eq!(post(&mut throttle, "-16"), None);
+        assert_eq!(end(&mut throttle), None);
+        assert_eq!(post(&mut throttle, "-15"), Some("-15".to_string()));
+    }
+
+    #[test]
+    fn drops_value_back_to_the_posted_one() {
+        let mut throttle = Throttle::default();
+        assert_eq!(post(&mut throttle, "-18"), Some("-18".to_string()));
+        assert_eq!(post(&mut throttle, "-17"), None);
+        assert_eq!(post(&mut throttle, "-18"), None);
+        assert_eq!(end(&mut throttle), None);
+    }
+
+    #[test]
+    fn stops_when_expired() {
+        let mut test = start("watch-expiry");
+        test.send(reading(-18.0));
+        assert_eq!(test.next_delivered(), "-18.0");
+        test.expire.take().unwrap().send(()).unwrap();
+        assert_eq!(test.next_delivered(), "stopped");
+        assert!(stop("watch-expiry").is_none());
+    }
+
+    #[test]
+    fn stops_on_request() {
+        let mut test = start("watch-stop");
+        test.send(reading(-18.0));
+        assert_eq!(test.next_delivered(), "-18.0");
+        assert_eq!(stop("watch-stop").map(|watched| watched.device), Some("Freezer".to_string()));
+        test.dropped.recv_timeout(Duration::from_secs(5)).unwrap();
+    }
+
+    #[test]
+    fn reports_failed_subscription() {
+        let mut test = start("watch-failure");
+        test.send(Err(AkcClientError::AkcError(401, "Please provide a valid authorization header".to_string())));
+        assert_eq!(test.next_delivered(), "failed");
+        assert!(stop("watch-failure").is_none());
+    }
+}
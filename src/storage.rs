@@ -0,0 +1,134 @@
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io;
+use std::path::PathBuf;
+
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use serde_json;
+
+use CONFIGURATION;
+
+pub trait Storage<V>: Send {
+    fn get(&self, key: &str) -> Option<&V>;
+    fn insert(&mut self, key: String, value: V);
+    fn remove(&mut self, key: &str) -> Option<V>;
+    fn keys(&self) -> Vec<String>;
+}
+
+pub struct MemoryStorage<V> {
+    values: HashMap<String, V>,
+}
+impl<V> MemoryStorage<V> {
+    pub fn new() -> MemoryStorage<V> {
+        MemoryStorage { values: HashMap::new() }
+    }
+}
+impl<V: Send> Storage<V> for MemoryStorage<V> {
+    fn get(&self, key: &str) -> Option<&V> {
+        self.values.get(key)
+    }
+    fn insert(&mut self, key: String, value: V) {
+        self.values.insert(key, value);
+    }
+    fn remove(&mut self, key: &str) -> Option<V> {
+        self.values.remove(key)
+    }
+    fn keys(&self) -> Vec<String> {
+        self.values.keys().cloned().collect()
+    }
+}
+
+/// Keeps every value in memory and rewrites the whole JSON file on each change.
+pub struct FileStorage<V> {
+    path: PathBuf,
+    values: HashMap<String, V>,
+}
+impl<V> FileStorage<V>
+    where V: Serialize + DeserializeOwned
+{
+    pub fn open(path: PathBuf) -> Result<FileStorage<V>, String> {
+        let values = match File::open(&path) {
+            Ok(file) => serde_json::from_reader(file).map_err(|err| format!("error reading {:?}: {:?}", path, err))?,
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => HashMap::new(),
+            Err(err) => return Err(format!("error opening {:?}: {:?}", path, err)),
+        };
+        Ok(FileStorage { path, values })
+    }
+
+    fn save(&self) {
+        let tmp_path = self.path.with_extension("tmp");
+        let result = File::create(&tmp_path)
+            .map_err(|err| format!("{:?}", err))
+            .and_then(|file| serde_json::to_writer(file, &self.values).map_err(|err| format!("{:?}", err)))
+            .and_then(|_| fs::rename(&tmp_path, &self.path).map_err(|err| format!("{:?}", err)));
+        if let Err(err) = result {
+            error!("error saving {:?}: {}", self.path, err);
+        }
+    }
+}
+impl<V> Storage<V> for FileStorage<V>
+    where V: Serialize + DeserializeOwned + Send
+{
+    fn get(&self, key: &str) -> Option<&V> {
+        self.values.get(key)
+    }
+    fn insert(&mut self, key: String, value: V) {
+        self.values.insert(key, value);
+        self.save();
+    }
+    fn remove(&mut self, key: &str) -> Option<V> {
+        let removed = self.values.remove(key);
+        if removed.is_some() {
+            self.save();
+        }
+        removed
+    }
+    fn keys(&self) -> Vec<String> {
+        self.values.keys().cloned().collect()
+    }
+}
+
+/// Opens the storage `name` where the configuration says, failing if its file can't be read.
+pub fn open<V>(name: &str) -> Result<Box<Storage<V>>, String>
+    where V: Serialize + DeserializeOwned + Send + 'static
+{
+    match CONFIGURATION.storage_path {
+        Some(ref storage_path) => {
+            let mut path = PathBuf::from(storage_path);
+            fs::create_dir_all(&path)
+                .map_err(|err| format!("error creating storage directory {:?}: {:?}", path, err))?;
+            path.push(format!("{}.json", name));
+            info!("opening storage {:?}", path);
+            Ok(Box::new(FileStorage::open(path)?))
+        }
+        None => {
+            warn!("no storage path configured, {} will be kept in memory", name);
+            Ok(Box::new(MemoryStorage::new()))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use uuid::Uuid;
+
+    use super::{Storage, FileStorage};
+
+    #[test]
+    fn file_storage_survives_reopening() {
+        let path = env::temp_dir().join(format!("akc-bot-{}.json", Uuid::new_v4().simple()));
+        {
+            let mut storage = FileStorage::<String>::open(path.clone()).unwrap();
+            storage.insert("kept".to_string(), "value".to_string());
+            storage.insert("removed".to_string(), "value".to_string());
+            storage.remove("removed");
+        }
+        let storage = FileStorage::<String>::open(path.clone()).unwrap();
+        assert_eq!(storage.get("kept"), Some(&"value".to_string()));
+        assert_eq!(storage.get("removed"), None);
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,97 @@
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use futures::Future;
+use futures::sync::oneshot;
+
+struct Deadline {
+    at: Instant,
+    fire: oneshot::Sender<()>,
+}
+impl PartialEq for Deadline {
+    fn eq(&self, other: &Deadline) -> bool {
+        self.at == other.at
+    }
+}
+impl Eq for Deadline {}
+impl PartialOrd for Deadline {
+    fn partial_cmp(&self, other: &Deadline) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl Ord for Deadline {
+    /// The heap keeps the earliest deadline on top.
+    fn cmp(&self, other: &Deadline) -> Ordering {
+        other.at.cmp(&self.at)
+    }
+}
+
+/// A single thread firing every deadline, so that waiting doesn't hold a thread of the pool.
+struct Timer {
+    deadlines: Mutex<BinaryHeap<Deadline>>,
+    changed: Condvar,
+}
+impl Timer {
+    fn run(&self) {
+        let mut deadlines = self.deadlines.lock().unwrap();
+        loop {
+            let now = Instant::now();
+            while deadlines.peek().map(|deadline| deadline.at <= now).unwrap_or(false) {
+                let _ = deadlines.pop().unwrap().fire.send(());
+            }
+            deadlines = match deadlines.peek().map(|deadline| deadline.at - now) {
+                Some(wait) => self.changed.wait_timeout(deadlines, wait).unwrap().0,
+                None => self.changed.wait(deadlines).unwrap(),
+            };
+        }
+    }
+}
+
+lazy_static! {
+    static ref TIMER: Arc<Timer> = {
+        let timer = Arc::new(Timer {
+                                 deadlines: Mutex::new(BinaryHeap::new()),
+                                 changed: Condvar::new(),
+                             });
+        let running = timer.clone();
+        thread::spawn(move || running.run());
+        timer
+    };
+}
+
+/// Resolves once `duration` has elapsed.
+pub fn sleep(duration: Duration) -> Box<Future<Item = (), Error = ()> + Send> {
+    let (fire, fired) = oneshot::channel();
+    TIMER
+        .deadlines
+        .lock()
+        .unwrap()
+        .push(Deadline {
+                  at: Instant::now() + duration,
+                  fire: fire,
+              });
+    TIMER.changed.notify_one();
+    fired.map_err(|_| ()).boxed()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use futures::Future;
+
+    use super::*;
+
+    #[test]
+    fn fires_in_order() {
+        let start = Instant::now();
+        let late = sleep(Duration::from_millis(50));
+        let early = sleep(Duration::from_millis(10));
+        early.wait().unwrap();
+        late.wait().unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(50));
+    }
+}